@@ -0,0 +1,217 @@
+//! Prints details of how the solvers read an input, for auditing answers.
+//!
+//! Usage: `inspect <mode> [input]`, where `input` defaults to the puzzle input of the mode's day.
+
+use std::{env, fs, process::ExitCode};
+
+use aoc_2024::{
+    day2::{self, Diagnosis},
+    day3::{self, Instruction, Semantics},
+    day4::{self, HitMap, SearchArea},
+    day5,
+    grid::{check_rows, Grid, Ragged},
+    input::normalize_line_endings,
+    Error,
+};
+use itertools::Itertools;
+
+const USAGE: &str = "usage: inspect <mode> [input]
+
+modes:
+    day2-diagnose   show how the problem dampener judges each day 2 report
+    day3-trace      list every day 3 instruction with the running part 2 total
+    day3-json       the same trace as JSON Lines
+    day4-matches    show the cells and hit counts of the day 4 matches
+    day4-color      show the day 4 matches highlighted in the whole grid
+    day5-explain    list the rules broken by each day 5 update";
+
+/// Reads an input and prints its details.
+type Mode = fn(&str) -> Result<(), Error>;
+
+fn day2_diagnose(input: &str) -> Result<(), Error> {
+    let diagnoses = day2::diagnose(input)?;
+
+    println!(
+        "{:>6}  {:<20}  {:<10}  {:>5}  levels",
+        "line", "verdict", "direction", "index"
+    );
+
+    let mut counts = [0; 3];
+    for (line, (levels, diagnosis)) in diagnoses.iter().enumerate() {
+        let (verdict, direction, index) = match *diagnosis {
+            Diagnosis::Safe { direction } => {
+                counts[0] += 1;
+                ("safe", direction.to_string(), String::new())
+            }
+            Diagnosis::SafeAfterRemoving { index, direction } => {
+                counts[1] += 1;
+                (
+                    "safe after removing",
+                    direction.to_string(),
+                    index.to_string(),
+                )
+            }
+            Diagnosis::Unsafe { first_bad_index } => {
+                counts[2] += 1;
+                ("unsafe", String::new(), first_bad_index.to_string())
+            }
+        };
+
+        println!(
+            "{:>6}  {verdict:<20}  {direction:<10}  {index:>5}  {}",
+            line + 1,
+            levels.iter().join(" ")
+        );
+    }
+
+    println!(
+        "{} safe, {} safe after removing a level, {} unsafe",
+        counts[0], counts[1], counts[2]
+    );
+
+    Ok(())
+}
+
+fn day3_trace(input: &str) -> Result<(), Error> {
+    let steps = day3::trace(input, Semantics::PART2);
+
+    println!(
+        "{:>8}  {:<16}  {:<8}  total",
+        "offset", "instruction", "enabled"
+    );
+    for step in &steps {
+        println!(
+            "{:>8}  {:<16}  {:<8}  {}",
+            step.token.offset,
+            step.token.instruction.to_string(),
+            if step.enabled { "yes" } else { "no" },
+            step.total
+        );
+    }
+
+    let part1 = steps
+        .iter()
+        .map(|step| match step.token.instruction {
+            Instruction::Mul(a, b) => a * b,
+            Instruction::Do | Instruction::Dont => 0,
+        })
+        .sum::<usize>();
+    println!(
+        "{} instructions, part 1 sum {part1}, part 2 sum {}",
+        steps.len(),
+        steps.last().map_or(0, |step| step.total)
+    );
+
+    Ok(())
+}
+
+fn day3_json(input: &str) -> Result<(), Error> {
+    for step in day3::trace(input, Semantics::PART2) {
+        println!("{}", step.to_json());
+    }
+
+    Ok(())
+}
+
+/// The day 4 matches of both parts, as hit maps.
+fn day4_hits(grid: Grid<'_>) -> [(String, HitMap<'_>); 2] {
+    let xmas = day4::find_xmas(&grid);
+    let mut part1 = HitMap::new(grid);
+    part1.add_words(SearchArea::default(), day4::XMAS.len(), &xmas);
+
+    let (templates, placements) = day4::find_x_mas(&grid);
+    let mut part2 = HitMap::new(grid);
+    part2.add_placements(&templates, &placements);
+
+    [
+        (format!("part 1: {} matches", xmas.len()), part1),
+        (format!("part 2: {} matches", placements.len()), part2),
+    ]
+}
+
+fn day4_matches(input: &str) -> Result<(), Error> {
+    let input = normalize_line_endings(input);
+    let input = check_rows(input.as_bytes(), Ragged::Reject)?;
+
+    for (title, hits) in day4_hits(Grid::new(&input)) {
+        println!(
+            "{title}\n{}\nhits per cell:\n{}",
+            hits.render(),
+            hits.render_heatmap()
+        );
+    }
+
+    Ok(())
+}
+
+fn day4_color(input: &str) -> Result<(), Error> {
+    let input = normalize_line_endings(input);
+    let input = check_rows(input.as_bytes(), Ragged::Reject)?;
+
+    for (title, hits) in day4_hits(Grid::new(&input)) {
+        println!("{title}\n{}", hits.render_ansi());
+    }
+
+    Ok(())
+}
+
+fn day5_explain(input: &str) -> Result<(), Error> {
+    let explanations = day5::explain(input)?;
+
+    for explanation in &explanations {
+        println!("{explanation}");
+    }
+
+    let correct = explanations
+        .iter()
+        .filter(|explanation| explanation.is_correct())
+        .collect::<Vec<_>>();
+    println!(
+        "{} of {} updates are correct, middle page sum {}",
+        correct.len(),
+        explanations.len(),
+        correct
+            .iter()
+            .map(|explanation| explanation.update.pages[explanation.update.pages.len() / 2])
+            .sum::<usize>()
+    );
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let (day, run): (u32, Mode) = match args.first().map(String::as_str) {
+        Some("day2-diagnose") => (2, day2_diagnose),
+        Some("day3-trace") => (3, day3_trace),
+        Some("day3-json") => (3, day3_json),
+        Some("day4-matches") => (4, day4_matches),
+        Some("day4-color") => (4, day4_color),
+        Some("day5-explain") => (5, day5_explain),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let path = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| format!("input/2024/day{day}.txt"));
+    let input = match fs::read_to_string(&path) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&input) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,41 +1,135 @@
 use aoc_runner_derive::aoc;
-use atoi::FromRadix10;
+use atoi::{FromRadix10, FromRadix10Checked};
 use itertools::Itertools;
 
-#[aoc(day1, part1)]
-pub fn part1(input: &str) -> usize {
-    let (mut col1, mut col2): (Vec<usize>, Vec<usize>) = input
-        .as_bytes()
-        .chunks(5 + 3 + 5 + 1)
-        .map(|line| {
-            (
-                usize::from_radix_10(&line[..5]).0,
-                usize::from_radix_10(&line[8..13]).0,
-            )
-        })
-        .unzip();
+use crate::{input::normalize_line_endings, Error, ErrorKind};
+
+/// Length of a line in the canonical puzzle input: two five digit IDs separated by three spaces.
+const FIXED_LINE_LEN: usize = 5 + 3 + 5 + 1;
+
+fn is_blank(c: u8) -> bool {
+    c == b' ' || c == b'\t'
+}
+
+fn is_fixed_width_line(line: &[u8]) -> bool {
+    let (line, newline) = match line.len() {
+        FIXED_LINE_LEN => line.split_at(FIXED_LINE_LEN - 1),
+        len if len == FIXED_LINE_LEN - 1 => (line, &b"\n"[..]),
+        _ => return false,
+    };
+
+    newline == b"\n"
+        && line[..5].iter().all(u8::is_ascii_digit)
+        && &line[5..8] == b"   "
+        && line[8..13].iter().all(u8::is_ascii_digit)
+}
+
+/// Fast path for the canonical layout. Returns `None` as soon as a line does not match it.
+fn parse_fixed_width(input: &[u8]) -> Option<(Vec<usize>, Vec<usize>)> {
+    let mut col1 = Vec::with_capacity(input.len() / FIXED_LINE_LEN + 1);
+    let mut col2 = Vec::with_capacity(input.len() / FIXED_LINE_LEN + 1);
+
+    for line in input.chunks(FIXED_LINE_LEN) {
+        if !is_fixed_width_line(line) {
+            return None;
+        }
+        col1.push(usize::from_radix_10(&line[..5]).0);
+        col2.push(usize::from_radix_10(&line[8..13]).0);
+    }
+
+    Some((col1, col2))
+}
+
+fn parse_id(line: &[u8], i: usize, line_no: usize) -> Result<(usize, usize), Error> {
+    let error = |kind| Error::new(line_no, i + 1, kind);
+
+    match usize::from_radix_10_checked(&line[i..]) {
+        (_, 0) => Err(error(ErrorKind::ExpectedNumber)),
+        (None, _) => Err(error(ErrorKind::NumberOverflow)),
+        (Some(id), r) => Ok((id, i + r)),
+    }
+}
+
+fn parse_line(line: &[u8], line_no: usize) -> Result<(usize, usize), Error> {
+    let skip_blanks = |i: usize| i + line[i..].iter().take_while(|&&c| is_blank(c)).count();
+
+    let i = skip_blanks(0);
+    let (left, i) = parse_id(line, i, line_no)?;
+
+    let separator_start = i;
+    let i = skip_blanks(i);
+    if i == separator_start {
+        return Err(Error::new(line_no, i + 1, ErrorKind::ExpectedSeparator));
+    }
+
+    let (right, i) = parse_id(line, i, line_no)?;
+
+    let i = skip_blanks(i);
+    if i != line.len() {
+        return Err(Error::new(line_no, i + 1, ErrorKind::TrailingCharacters));
+    }
+
+    Ok((left, right))
+}
+
+fn parse_variable_width(input: &[u8]) -> Result<(Vec<usize>, Vec<usize>), Error> {
+    let mut col1 = Vec::new();
+    let mut col2 = Vec::new();
+
+    for (line_no, line) in input.split(|&c| c == b'\n').enumerate() {
+        if line.iter().all(|&c| is_blank(c)) {
+            continue;
+        }
+        let (left, right) = parse_line(line, line_no + 1)?;
+        col1.push(left);
+        col2.push(right);
+    }
+
+    Ok((col1, col2))
+}
+
+/// Parses the two location-ID lists.
+///
+/// IDs may have any number of digits and be separated by any run of spaces or tabs. Blank lines
+/// are ignored. Lines may end in `\n` or `\r\n`. Inputs in the canonical fixed-width layout take
+/// a faster path.
+pub fn parse_lists(input: &str) -> Result<(Vec<usize>, Vec<usize>), Error> {
+    let input = normalize_line_endings(input);
+    let input = input.as_bytes();
+
+    match parse_fixed_width(input) {
+        Some(lists) => Ok(lists),
+        None => parse_variable_width(input),
+    }
+}
+
+/// An answer too large for a `usize` belongs to no line in particular, so it is reported at the
+/// end of the input.
+fn answer_overflow(input: &str) -> Error {
+    Error::at(input.as_bytes(), input.len(), ErrorKind::NumberOverflow)
+}
+
+pub fn try_part1(input: &str) -> Result<usize, Error> {
+    let (mut col1, mut col2) = parse_lists(input)?;
 
     col1.sort();
     col2.sort();
 
     col1.into_iter()
-        .zip(col2.into_iter())
-        .map(|(left, right)| left.abs_diff(right))
-        .sum::<usize>()
+        .zip(col2)
+        .try_fold(0usize, |sum, (left, right)| {
+            sum.checked_add(left.abs_diff(right))
+        })
+        .ok_or_else(|| answer_overflow(input))
 }
 
-#[aoc(day1, part2)]
-pub fn part2(input: &str) -> usize {
-    let (mut col1, mut col2): (Vec<usize>, Vec<usize>) = input
-        .as_bytes()
-        .chunks(5 + 3 + 5 + 1)
-        .map(|line| {
-            (
-                usize::from_radix_10(&line[..5]).0,
-                usize::from_radix_10(&line[8..13]).0,
-            )
-        })
-        .unzip();
+#[aoc(day1, part1)]
+pub fn part1(input: &str) -> usize {
+    try_part1(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+pub fn try_part2(input: &str) -> Result<usize, Error> {
+    let (mut col1, mut col2) = parse_lists(input)?;
 
     col1.sort();
     col2.sort();
@@ -45,16 +139,24 @@ pub fn part2(input: &str) -> usize {
         .merge_join_by(col2.into_iter().dedup_with_count(), |(_, a), (_, b)| {
             a.cmp(b)
         })
-        .map(|r| match r {
-            itertools::EitherOrBoth::Both((a, c), (b, _)) => a * b * c,
-            _ => 0,
+        .try_fold(0usize, |sum, r| match r {
+            itertools::EitherOrBoth::Both((a, c), (b, _)) => {
+                sum.checked_add(a.checked_mul(b)?.checked_mul(c)?)
+            }
+            _ => Some(sum),
         })
-        .sum::<usize>()
+        .ok_or_else(|| answer_overflow(input))
+}
+
+#[aoc(day1, part2)]
+pub fn part2(input: &str) -> usize {
+    try_part2(input).unwrap_or_else(|err| panic!("{err}"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::line_ending_variants;
 
     #[test]
     fn part1_example() {
@@ -71,4 +173,67 @@ mod tests {
             "31"
         );
     }
+
+    #[test]
+    fn variable_width_example() {
+        let input = "3   4\n4\t3\n2 5\n1 \t 3\n3       9\n  3   3";
+        assert_eq!(part1(input), 11);
+        assert_eq!(part2(input), 31);
+    }
+
+    #[test]
+    fn fixed_width_without_trailing_newline() {
+        assert_eq!(
+            parse_lists("00003   00004\n12345   00003"),
+            Ok((vec![3, 12345], vec![4, 3]))
+        );
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert_eq!(
+            parse_lists("3   4\n4   x\n"),
+            Err(Error::new(2, 5, ErrorKind::ExpectedNumber))
+        );
+        assert_eq!(
+            parse_lists("34\n").unwrap_err().kind,
+            ErrorKind::ExpectedSeparator
+        );
+        assert_eq!(
+            parse_lists("3 4 5\n").unwrap_err().kind,
+            ErrorKind::TrailingCharacters
+        );
+        assert_eq!(
+            parse_lists("99999999999999999999999 1\n").unwrap_err().kind,
+            ErrorKind::NumberOverflow
+        );
+    }
+
+    #[test]
+    fn answer_overflow() {
+        assert_eq!(
+            try_part1("18446744073709551615 0\n18446744073709551615 0\n"),
+            Err(Error::new(3, 1, ErrorKind::NumberOverflow))
+        );
+        assert_eq!(
+            try_part1("18446744073709551615 0\n"),
+            Ok(18446744073709551615)
+        );
+        assert_eq!(
+            try_part2("9223372036854775808 9223372036854775808\n1 9223372036854775808\n"),
+            Err(Error::new(3, 1, ErrorKind::NumberOverflow))
+        );
+        assert_eq!(
+            try_part2("18446744073709551615 18446744073709551615\n1 1\n"),
+            Err(Error::new(3, 1, ErrorKind::NumberOverflow))
+        );
+    }
+
+    #[test]
+    fn line_endings() {
+        for input in line_ending_variants("3   4\n4   3\n2   5\n1   3\n3   9\n3   3\n") {
+            assert_eq!(try_part1(&input), Ok(11));
+            assert_eq!(try_part2(&input), Ok(31));
+        }
+    }
 }
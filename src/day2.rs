@@ -1,71 +1,169 @@
+use std::{fmt, ops::RangeInclusive};
+
 use aoc_runner_derive::aoc;
-use atoi::FromRadix10;
+use atoi::{FromRadix10Checked, FromRadix10SignedChecked};
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{digit1, line_ending, not_line_ending},
-    combinator::{eof, iterator, map, value, verify},
+    character::complete::{digit1, line_ending},
+    combinator::{cut, eof, iterator, opt, recognize, value, verify},
+    error,
+    multi::{many0_count, separated_list1},
     sequence::{pair, preceded},
-    IResult,
+    Err, IResult,
 };
 
+use crate::{input::normalize_line_endings, Error, ErrorKind};
+
+/// Fails with nom's `TooLarge` when the level doesn't fit in an `i64`, so that inputs with and
+/// without negative levels accept the same numbers.
 fn parse_num(i: &[u8]) -> IResult<&[u8], usize> {
-    map(digit1, |digit_str: &[u8]| usize::from_radix_10(digit_str).0)(i)
+    let (rest, digit_str) = digit1(i)?;
+    match i64::from_radix_10_checked(digit_str) {
+        (Some(num), _) => Ok((rest, num as usize)),
+        (None, _) => Err(Err::Failure(error::Error::new(
+            i,
+            error::ErrorKind::TooLarge,
+        ))),
+    }
 }
 
-fn increasing(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
+/// Fails with nom's `TooLarge` when the level doesn't fit in an `i64`.
+fn parse_signed_num(i: &[u8]) -> IResult<&[u8], i64> {
+    let (rest, digit_str) = recognize(pair(opt(tag("-")), digit1))(i)?;
+    match i64::from_radix_10_signed_checked(digit_str) {
+        (Some(num), _) => Ok((rest, num)),
+        (None, _) => Err(Err::Failure(error::Error::new(
+            i,
+            error::ErrorKind::TooLarge,
+        ))),
+    }
+}
+
+/// A level type for the nom solvers: `usize` for the usual unsigned inputs, and `i64` for inputs
+/// with negative levels.
+trait Level: Copy {
+    fn parse(i: &[u8]) -> IResult<&[u8], Self>;
+
+    /// Returns whether going from `from` to `to` is an increase of 1 to 3.
+    fn is_safe_increase(from: Self, to: Self) -> bool;
+}
+
+impl Level for usize {
+    fn parse(i: &[u8]) -> IResult<&[u8], Self> {
+        parse_num(i)
+    }
+
+    fn is_safe_increase(from: Self, to: Self) -> bool {
+        matches!(to.checked_sub(from), Some(1..=3))
+    }
+}
+
+impl Level for i64 {
+    fn parse(i: &[u8]) -> IResult<&[u8], Self> {
+        parse_signed_num(i)
+    }
+
+    fn is_safe_increase(from: Self, to: Self) -> bool {
+        matches!(to.checked_sub(from), Some(1..=3))
+    }
+}
+
+fn increasing<L: Level>(previous_num: L) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
     move |i: &[u8]| {
-        let (i, num) = verify(preceded(tag(" "), parse_num), |&num| {
-            matches!(num.wrapping_sub(previous_num), 1..=3)
+        let (i, num) = verify(preceded(tag(" "), L::parse), |&num| {
+            L::is_safe_increase(previous_num, num)
         })(i)?;
 
         alt((value((), alt((line_ending, eof))), increasing(num)))(i)
     }
 }
 
-fn decreasing(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
+fn decreasing<L: Level>(previous_num: L) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
     move |i: &[u8]| {
-        let (i, num) = verify(preceded(tag(" "), parse_num), |&num| {
-            matches!(previous_num.wrapping_sub(num), 1..=3)
+        let (i, num) = verify(preceded(tag(" "), L::parse), |&num| {
+            L::is_safe_increase(num, previous_num)
         })(i)?;
 
         alt((value((), alt((line_ending, eof))), decreasing(num)))(i)
     }
 }
 
-fn parse_line(i: &[u8]) -> IResult<&[u8], bool> {
-    let (i, head) = parse_num(i)?;
+/// Skips the rest of an unsafe report. Anything but levels up to the line ending is a failure,
+/// so a malformed report is an error rather than an unsafe one.
+fn unsafe_rest<L: Level>(i: &[u8]) -> IResult<&[u8], bool> {
+    value(
+        false,
+        pair(
+            many0_count(preceded(tag(" "), L::parse)),
+            cut(alt((line_ending, eof))),
+        ),
+    )(i)
+}
+
+fn parse_line<L: Level>(i: &[u8]) -> IResult<&[u8], bool> {
+    let (i, head) = L::parse(i)?;
 
     alt((
         value(true, increasing(head)),
         value(true, decreasing(head)),
-        value(false, pair(not_line_ending, alt((line_ending, eof)))),
+        unsafe_rest::<L>,
     ))(i)
 }
 
-#[aoc(day2, part1)]
-fn part1<'a>(input: &str) -> usize {
-    let mut it = iterator(input.as_bytes(), parse_line);
+fn count_safe(input: &str, parser: fn(&[u8]) -> IResult<&[u8], bool>) -> Result<usize, Error> {
+    let input = normalize_line_endings(input);
+    let input = input.as_bytes();
+
+    let mut it = iterator(input, parser);
 
     let sum = it.map(|x| if x { 1 } else { 0 }).sum::<usize>();
 
-    assert!(it.finish().unwrap().0.is_empty());
+    let (rest, ()) = it.finish().map_err(|err| {
+        let kind = match err {
+            nom::Err::Failure(_) => ErrorKind::ExpectedLineEnding,
+            _ => ErrorKind::ExpectedNumber,
+        };
+        Error::from_nom(input, err, kind)
+    })?;
+
+    if !rest.is_empty() {
+        return Err(Error::at_rest(input, rest, ErrorKind::ExpectedNumber));
+    }
+
+    Ok(sum)
+}
 
-    sum
+/// Inputs without a minus sign are solved with unsigned levels, which is faster.
+fn has_negative_levels(input: &str) -> bool {
+    memchr::memchr(b'-', input.as_bytes()).is_some()
 }
 
-fn skip_and_increasing(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
+pub fn try_part1(input: &str) -> Result<usize, Error> {
+    if has_negative_levels(input) {
+        count_safe(input, parse_line::<i64>)
+    } else {
+        count_safe(input, parse_line::<usize>)
+    }
+}
+
+#[aoc(day2, part1)]
+fn part1(input: &str) -> usize {
+    try_part1(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+fn skip_and_increasing<L: Level>(previous_num: L) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
     move |i: &[u8]| {
-        let (i, _) = preceded(tag(" "), digit1)(i)?;
+        let (i, _) = preceded(tag(" "), L::parse)(i)?;
 
         alt((value((), alt((line_ending, eof))), increasing(previous_num)))(i)
     }
 }
 
-fn increasing_with_safety(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
+fn increasing_with_safety<L: Level>(previous_num: L) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
     move |i: &[u8]| {
-        let (i, num) = verify(preceded(tag(" "), parse_num), |&num| {
-            matches!(num.wrapping_sub(previous_num), 1..=3)
+        let (i, num) = verify(preceded(tag(" "), L::parse), |&num| {
+            L::is_safe_increase(previous_num, num)
         })(i)?;
 
         alt((
@@ -76,18 +174,18 @@ fn increasing_with_safety(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&
     }
 }
 
-fn skip_and_decreasing(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
+fn skip_and_decreasing<L: Level>(previous_num: L) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
     move |i: &[u8]| {
-        let (i, _) = preceded(tag(" "), digit1)(i)?;
+        let (i, _) = preceded(tag(" "), L::parse)(i)?;
 
         alt((value((), alt((line_ending, eof))), decreasing(previous_num)))(i)
     }
 }
 
-fn decreasing_with_safety(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
+fn decreasing_with_safety<L: Level>(previous_num: L) -> impl FnMut(&[u8]) -> IResult<&[u8], ()> {
     move |i: &[u8]| {
-        let (i, num) = verify(preceded(tag(" "), parse_num), |&num| {
-            matches!(previous_num.wrapping_sub(num), 1..=3)
+        let (i, num) = verify(preceded(tag(" "), L::parse), |&num| {
+            L::is_safe_increase(num, previous_num)
         })(i)?;
 
         alt((
@@ -98,33 +196,329 @@ fn decreasing_with_safety(previous_num: usize) -> impl FnMut(&[u8]) -> IResult<&
     }
 }
 
-fn parse_line_with_safety(i: &[u8]) -> IResult<&[u8], bool> {
-    let (i, head) = parse_num(i)?;
+fn parse_line_with_safety<L: Level>(i: &[u8]) -> IResult<&[u8], bool> {
+    let (i, head) = L::parse(i)?;
 
     alt((
         value(true, increasing_with_safety(head)),
         value(true, decreasing_with_safety(head)),
         value(true, skip_and_increasing(head)),
         value(true, skip_and_decreasing(head)),
-        preceded(tag(" "), parse_line),
-        value(false, pair(not_line_ending, alt((line_ending, eof)))),
+        preceded(tag(" "), parse_line::<L>),
+        unsafe_rest::<L>,
     ))(i)
 }
 
+pub fn try_part2(input: &str) -> Result<usize, Error> {
+    if has_negative_levels(input) {
+        count_safe(input, parse_line_with_safety::<i64>)
+    } else {
+        count_safe(input, parse_line_with_safety::<usize>)
+    }
+}
+
 #[aoc(day2, part2)]
 fn part2(input: &str) -> usize {
-    let mut it = iterator(input.as_bytes(), parse_line_with_safety);
+    try_part2(input).unwrap_or_else(|err| panic!("{err}"))
+}
 
-    let sum = it.map(|x| if x { 1 } else { 0 }).sum::<usize>();
+fn parse_levels(i: &[u8]) -> IResult<&[u8], Vec<i64>> {
+    separated_list1(tag(" "), parse_signed_num)(i)
+}
+
+/// Parses every report into its levels.
+pub fn parse_reports(input: &str) -> Result<Vec<Vec<i64>>, Error> {
+    let input = normalize_line_endings(input);
+    let input = input.as_bytes();
+
+    let mut rest = input;
+    let mut reports = Vec::new();
+
+    while !rest.is_empty() {
+        let (r, levels) = parse_levels(rest)
+            .map_err(|err| Error::from_nom(input, err, ErrorKind::ExpectedNumber))?;
+        let (r, _) = alt((line_ending, eof))(r)
+            .map_err(|err| Error::from_nom(input, err, ErrorKind::ExpectedLineEnding))?;
+
+        reports.push(levels);
+        rest = r;
+    }
+
+    Ok(reports)
+}
+
+/// Returns whether `levels` can be made safe by removing at most `max_removals` of them. A report
+/// is safe when its levels are all increasing or all decreasing, with every step between
+/// adjacent levels in `step`.
+pub fn is_safe(levels: &[i64], max_removals: usize, step: RangeInclusive<u64>) -> bool {
+    let fits = |from: i64, to: i64| {
+        u64::try_from(i128::from(to) - i128::from(from)).is_ok_and(|diff| step.contains(&diff))
+    };
+
+    let n = levels.len();
+    let window = max_removals.saturating_add(1);
+    if n <= window {
+        return true;
+    }
+
+    [true, false].into_iter().any(|increasing| {
+        // removals[j] is the fewest levels to remove before `j` for `levels[..=j]` to be safe
+        // while keeping `levels[j]`. Keeping `levels[i]` then `levels[j]` removes everything
+        // between them, so only the `max_removals + 1` levels before `j` are worth trying.
+        let mut removals = Vec::with_capacity(n);
 
-    assert!(it.finish().unwrap().0.is_empty());
+        for j in 0..n {
+            let best = (j.saturating_sub(window)..j)
+                .filter(|&i| {
+                    if increasing {
+                        fits(levels[i], levels[j])
+                    } else {
+                        fits(levels[j], levels[i])
+                    }
+                })
+                .map(|i| removals[i] + (j - i - 1))
+                .fold(j, usize::min);
+            removals.push(best);
+        }
+
+        removals
+            .iter()
+            .enumerate()
+            .any(|(j, &removed)| removed + (n - 1 - j) <= max_removals)
+    })
+}
+
+pub fn try_part1_generic(input: &str) -> Result<usize, Error> {
+    Ok(parse_reports(input)?
+        .iter()
+        .filter(|levels| is_safe(levels, 0, 1..=3))
+        .count())
+}
+
+#[aoc(day2, part1, Generic)]
+fn part1_generic(input: &str) -> usize {
+    try_part1_generic(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+pub fn try_part2_generic(input: &str) -> Result<usize, Error> {
+    Ok(parse_reports(input)?
+        .iter()
+        .filter(|levels| is_safe(levels, 1, 1..=3))
+        .count())
+}
+
+#[aoc(day2, part2, Generic)]
+fn part2_generic(input: &str) -> usize {
+    try_part2_generic(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// The dynamic program of [`is_safe`] with one removal, run one level at a time. With a single
+/// removal it only ever looks back two levels, so the state is constant size.
+#[derive(Debug, Clone, Copy, Default)]
+struct ReportState {
+    /// The last two levels, oldest first.
+    levels: [i64; 2],
+    /// For the increasing and the decreasing direction, the fewest removals (capped at 2) that
+    /// keep the report safe up to and including each of the last two levels.
+    removals: [[u8; 2]; 2],
+    len: usize,
+}
+
+impl ReportState {
+    fn push(&mut self, level: i64) {
+        let fits = |direction: usize, from: i64, to: i64| {
+            let step = if direction == 0 {
+                to.checked_sub(from)
+            } else {
+                from.checked_sub(to)
+            };
+            matches!(step, Some(1..=3))
+        };
+
+        for (direction, removals) in self.removals.iter_mut().enumerate() {
+            let mut best = self.len.min(2) as u8;
+            if self.len >= 1 && fits(direction, self.levels[1], level) {
+                best = best.min(removals[1]);
+            }
+            if self.len >= 2 && fits(direction, self.levels[0], level) {
+                best = best.min(removals[0] + 1);
+            }
+            *removals = [removals[1], best.min(2)];
+        }
+
+        self.levels = [self.levels[1], level];
+        self.len += 1;
+    }
+
+    fn is_safe(&self) -> bool {
+        self.len <= 2
+            || self
+                .removals
+                .iter()
+                .any(|removals| removals[1] <= 1 || removals[0] == 0)
+    }
+}
+
+pub fn try_part2_state_machine(input: &str) -> Result<usize, Error> {
+    let input = normalize_line_endings(input);
+    let input = input.as_bytes();
+
+    let mut safe = 0;
+    let mut report = ReportState::default();
+    // The level being read, with the offset of its first character, and the offset of its minus
+    // sign if it is negative.
+    let mut level: Option<(i64, usize)> = None;
+    let mut minus: Option<usize> = None;
+
+    let last_line_ending = (!input.is_empty() && !input.ends_with(b"\n")).then_some(b'\n');
+
+    for (i, c) in input.iter().copied().chain(last_line_ending).enumerate() {
+        match c {
+            b'-' if level.is_none() && minus.is_none() => minus = Some(i),
+            b'0'..=b'9' => {
+                let (value, start) = level.unwrap_or((0, minus.unwrap_or(i)));
+                let digit = i64::from(c - b'0');
+                let value = value
+                    .checked_mul(10)
+                    .and_then(|value| {
+                        if minus.is_some() {
+                            value.checked_sub(digit)
+                        } else {
+                            value.checked_add(digit)
+                        }
+                    })
+                    .ok_or_else(|| Error::at(input, start, ErrorKind::NumberOverflow))?;
+                level = Some((value, start));
+            }
+            b' ' | b'\n' => {
+                let Some((value, _)) = level.take() else {
+                    return Err(Error::at(input, i, ErrorKind::ExpectedNumber));
+                };
+                report.push(value);
+                minus = None;
+
+                if c == b'\n' {
+                    if report.is_safe() {
+                        safe += 1;
+                    }
+                    report = ReportState::default();
+                }
+            }
+            _ if level.is_some() => {
+                return Err(Error::at(input, i, ErrorKind::ExpectedSeparator));
+            }
+            _ => return Err(Error::at(input, i, ErrorKind::ExpectedNumber)),
+        }
+    }
+
+    Ok(safe)
+}
+
+#[aoc(day2, part2, StateMachine)]
+fn part2_state_machine(input: &str) -> usize {
+    try_part2_state_machine(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Increasing,
+    Decreasing,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Increasing => "increasing",
+            Direction::Decreasing => "decreasing",
+        })
+    }
+}
+
+/// How the problem dampener judges a report. Reports with a single level count as increasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnosis {
+    Safe {
+        direction: Direction,
+    },
+    /// Removing the level at `index`, the first one for which that works, makes the report safe.
+    SafeAfterRemoving {
+        index: usize,
+        direction: Direction,
+    },
+    /// Even with one level removed the report is unsafe. `first_bad_index` is the first level
+    /// that breaks the longest safe prefix of the report.
+    Unsafe {
+        first_bad_index: usize,
+    },
+}
+
+/// Returns the index of the first level, ignoring the one at `skip`, whose step from the previous
+/// level breaks `direction`.
+fn first_bad_index(levels: &[i64], skip: Option<usize>, direction: Direction) -> Option<usize> {
+    let mut kept = levels.iter().enumerate().filter(|&(i, _)| Some(i) != skip);
+
+    let (_, mut previous) = kept.next()?;
+
+    for (i, level) in kept {
+        let step = match direction {
+            Direction::Increasing => level.checked_sub(*previous),
+            Direction::Decreasing => previous.checked_sub(*level),
+        };
+        if !matches!(step, Some(1..=3)) {
+            return Some(i);
+        }
+        previous = level;
+    }
+
+    None
+}
+
+/// Diagnoses a report with the rules of part 2.
+pub fn diagnose_report(levels: &[i64]) -> Diagnosis {
+    const DIRECTIONS: [Direction; 2] = [Direction::Increasing, Direction::Decreasing];
+
+    let bad = DIRECTIONS.map(|direction| first_bad_index(levels, None, direction));
+
+    let mut candidates = Vec::new();
+    for (direction, bad) in DIRECTIONS.into_iter().zip(bad) {
+        match bad {
+            None => return Diagnosis::Safe { direction },
+            // The step into `bad` stays unless one of its two levels is removed.
+            Some(bad) => candidates.extend([bad - 1, bad]),
+        }
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    for index in candidates {
+        for direction in DIRECTIONS {
+            if first_bad_index(levels, Some(index), direction).is_none() {
+                return Diagnosis::SafeAfterRemoving { index, direction };
+            }
+        }
+    }
+
+    Diagnosis::Unsafe {
+        first_bad_index: bad.into_iter().flatten().max().unwrap_or(0),
+    }
+}
 
-    sum
+/// Parses every report and diagnoses it.
+pub fn diagnose(input: &str) -> Result<Vec<(Vec<i64>, Diagnosis)>, Error> {
+    Ok(parse_reports(input)?
+        .into_iter()
+        .map(|levels| {
+            let diagnosis = diagnose_report(&levels);
+            (levels, diagnosis)
+        })
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{input::line_ending_variants, test_util::XorShift};
+    use itertools::Itertools;
 
     #[test]
     fn part1_example() {
@@ -141,4 +535,341 @@ mod tests {
             4
         );
     }
+
+    #[test]
+    fn malformed_report() {
+        assert_eq!(
+            try_part1("7 6 4 2 1\nx 2 7 8 9\n"),
+            Err(Error::new(2, 1, ErrorKind::ExpectedNumber))
+        );
+        assert_eq!(
+            try_part2("7 6 4 2 1\n\n"),
+            Err(Error::new(2, 1, ErrorKind::ExpectedNumber))
+        );
+
+        for (input, column) in [("1,2,3\n", 2), ("1 2 x\n", 4), ("1 2 3 \n", 6)] {
+            let expected = Err(Error::new(1, column, ErrorKind::ExpectedLineEnding));
+            assert_eq!(try_part1(input), expected, "{input:?}");
+            assert_eq!(try_part2(input), expected, "{input:?}");
+            assert_eq!(try_part1_generic(input), expected, "{input:?}");
+            assert_eq!(try_part2_generic(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn number_overflow() {
+        for (input, column) in [
+            ("99999999999999999999999 1\n", 1),
+            ("1 2 99999999999999999999999\n", 5),
+            ("1 -99999999999999999999999\n", 3),
+        ] {
+            let expected = Err(Error::new(1, column, ErrorKind::NumberOverflow));
+            assert_eq!(try_part1(input), expected, "{input:?}");
+            assert_eq!(try_part2(input), expected, "{input:?}");
+            assert_eq!(try_part1_generic(input), expected, "{input:?}");
+            assert_eq!(try_part2_generic(input), expected, "{input:?}");
+            assert_eq!(try_part2_state_machine(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn line_endings() {
+        for input in line_ending_variants(
+            "7 6 4 2 1\n1 2 7 8 9\n9 7 6 2 1\n1 3 2 4 5\n8 6 4 4 1\n1 3 6 7 9\n",
+        ) {
+            assert_eq!(try_part1(&input), Ok(2));
+            assert_eq!(try_part2(&input), Ok(4));
+        }
+    }
+
+    /// Brute force reference for `is_safe`: tries every way of removing levels.
+    fn is_safe_brute_force(levels: &[i64], max_removals: usize, step: RangeInclusive<u64>) -> bool {
+        let n = levels.len();
+        (0u32..1 << n)
+            .filter(|removed| removed.count_ones() as usize <= max_removals)
+            .any(|removed| {
+                let kept = (0..n)
+                    .filter(|i| removed & (1 << i) == 0)
+                    .map(|i| levels[i])
+                    .collect::<Vec<_>>();
+                let steps = kept.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+                steps.iter().all(|&s| s >= 0 && step.contains(&(s as u64)))
+                    || steps.iter().all(|&s| s <= 0 && step.contains(&(-s as u64)))
+            })
+    }
+
+    /// 2000 reports of 5 to 8 levels, each a random step of at most 4 from the previous one.
+    /// Levels stay non-negative unless `negative` is set.
+    fn random_reports(rng: &mut XorShift, negative: bool) -> String {
+        (0..2000)
+            .map(|_| {
+                let mut level = if negative {
+                    rng.below(9) as i64 - 4
+                } else {
+                    rng.below(20) as i64
+                };
+                (0..5 + rng.below(4))
+                    .map(|_| {
+                        level += rng.below(9) as i64 - 4;
+                        if !negative {
+                            level = level.max(0);
+                        }
+                        level.to_string()
+                    })
+                    .join(" ")
+            })
+            .join("\n")
+    }
+
+    #[test]
+    fn generic_example() {
+        let input = "7 6 4 2 1\n1 2 7 8 9\n9 7 6 2 1\n1 3 2 4 5\n8 6 4 4 1\n1 3 6 7 9\n";
+        assert_eq!(part1_generic(input), 2);
+        assert_eq!(part2_generic(input), 4);
+    }
+
+    #[test]
+    fn generic_matches_nom() {
+        let mut rng = XorShift::new(2024);
+
+        let input = random_reports(&mut rng, false);
+
+        assert_eq!(part1_generic(&input), part1(&input));
+        assert_eq!(part2_generic(&input), part2(&input));
+    }
+
+    #[test]
+    fn is_safe_matches_brute_force() {
+        let mut rng = XorShift::new(7);
+
+        for _ in 0..2000 {
+            let levels = (0..rng.below(9))
+                .map(|_| rng.below(12) as i64 - 6)
+                .collect::<Vec<_>>();
+            let max_removals = rng.below(4) as usize;
+            let low = rng.below(3);
+            let step = low..=low + rng.below(4);
+
+            assert_eq!(
+                is_safe(&levels, max_removals, step.clone()),
+                is_safe_brute_force(&levels, max_removals, step.clone()),
+                "{levels:?} {max_removals} {step:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn is_safe_unlimited_removals() {
+        assert!(is_safe(&[1, 5, 9], usize::MAX, 1..=3));
+        assert!(is_safe(&[1, 5, 9], usize::MAX - 1, 1..=3));
+    }
+
+    #[test]
+    fn diagnose_example() {
+        let diagnoses =
+            diagnose("7 6 4 2 1\n1 2 7 8 9\n9 7 6 2 1\n1 3 2 4 5\n8 6 4 4 1\n1 3 6 7 9\n")
+                .unwrap()
+                .into_iter()
+                .map(|(_, diagnosis)| diagnosis)
+                .collect::<Vec<_>>();
+
+        assert_eq!(
+            diagnoses,
+            [
+                Diagnosis::Safe {
+                    direction: Direction::Decreasing
+                },
+                Diagnosis::Unsafe { first_bad_index: 2 },
+                Diagnosis::Unsafe { first_bad_index: 3 },
+                Diagnosis::SafeAfterRemoving {
+                    index: 1,
+                    direction: Direction::Increasing
+                },
+                Diagnosis::SafeAfterRemoving {
+                    index: 2,
+                    direction: Direction::Decreasing
+                },
+                Diagnosis::Safe {
+                    direction: Direction::Increasing
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnose_extreme_levels() {
+        assert_eq!(
+            diagnose_report(&[i64::MIN, i64::MAX]),
+            Diagnosis::SafeAfterRemoving {
+                index: 0,
+                direction: Direction::Increasing
+            }
+        );
+        assert_eq!(
+            diagnose_report(&[i64::MAX, i64::MIN, i64::MAX]),
+            Diagnosis::Unsafe { first_bad_index: 1 }
+        );
+    }
+
+    #[test]
+    fn diagnose_matches_is_safe() {
+        let mut rng = XorShift::new(42);
+
+        for _ in 0..5000 {
+            let levels = (0..1 + rng.below(7))
+                .map(|_| rng.below(10) as i64)
+                .collect::<Vec<_>>();
+
+            match diagnose_report(&levels) {
+                Diagnosis::Safe { .. } => assert!(is_safe(&levels, 0, 1..=3), "{levels:?}"),
+                Diagnosis::SafeAfterRemoving { index, .. } => {
+                    assert!(!is_safe(&levels, 0, 1..=3), "{levels:?}");
+                    let mut removed = levels.clone();
+                    removed.remove(index);
+                    assert!(is_safe(&removed, 0, 1..=3), "{levels:?}");
+                    for earlier in 0..index {
+                        let mut removed = levels.clone();
+                        removed.remove(earlier);
+                        assert!(!is_safe(&removed, 0, 1..=3), "{levels:?}");
+                    }
+                }
+                Diagnosis::Unsafe { .. } => assert!(!is_safe(&levels, 1, 1..=3), "{levels:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn state_machine_matches_nom() {
+        let mut rng = XorShift::new(11);
+
+        let input = random_reports(&mut rng, false);
+
+        assert_eq!(
+            part2_state_machine(
+                "7 6 4 2 1\n1 2 7 8 9\n9 7 6 2 1\n1 3 2 4 5\n8 6 4 4 1\n1 3 6 7 9\n"
+            ),
+            4
+        );
+        assert_eq!(part2_state_machine(&input), part2(&input));
+    }
+
+    #[test]
+    fn state_machine_long_reports() {
+        // A million levels rising by one, with a dip that the dampener can remove, then the
+        // same with two dips, which it can't.
+        let report = |dips: &[usize]| {
+            (0..1_000_000)
+                .map(|i| if dips.contains(&i) { 0 } else { i })
+                .join(" ")
+        };
+        let input = format!("{}\n{}\n", report(&[500_000]), report(&[1000, 500_000]));
+
+        assert_eq!(try_part2_state_machine(&input), Ok(1));
+    }
+
+    #[test]
+    fn state_machine_malformed_report() {
+        assert_eq!(
+            try_part2_state_machine("1 2 3\n4 5,6\n"),
+            Err(Error::new(2, 4, ErrorKind::ExpectedSeparator))
+        );
+        assert_eq!(
+            try_part2_state_machine("1 2 3\n4  5\n"),
+            Err(Error::new(2, 3, ErrorKind::ExpectedNumber))
+        );
+    }
+
+    #[test]
+    fn negative_levels() {
+        let input = "-3 -1 2 4\n4 2 -1 -3\n-1 1 5\n-2 -2 -2\n0 -3 -6 -4 -8\n";
+
+        assert_eq!(try_part1(input), Ok(2));
+        assert_eq!(try_part1_generic(input), Ok(2));
+        assert_eq!(try_part2(input), Ok(4));
+        assert_eq!(try_part2_generic(input), Ok(4));
+        assert_eq!(try_part2_state_machine(input), Ok(4));
+    }
+
+    #[test]
+    fn negative_levels_match_generic() {
+        let mut rng = XorShift::new(12);
+
+        let input = random_reports(&mut rng, true);
+
+        assert_eq!(part1(&input), part1_generic(&input));
+        assert_eq!(part2(&input), part2_generic(&input));
+        assert_eq!(part2_state_machine(&input), part2_generic(&input));
+    }
+
+    #[test]
+    fn extreme_levels() {
+        for input in [
+            "9223372036854775807 -9223372036854775808\n",
+            "-9223372036854775808 9223372036854775807\n",
+        ] {
+            assert_eq!(try_part1(input), Ok(0), "{input:?}");
+            assert_eq!(try_part1_generic(input), Ok(0), "{input:?}");
+            assert_eq!(try_part2(input), Ok(1), "{input:?}");
+            assert_eq!(try_part2_generic(input), Ok(1), "{input:?}");
+            assert_eq!(try_part2_state_machine(input), Ok(1), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn unsigned_and_signed_levels_agree() {
+        // The same reports, solved with unsigned levels and, with a negative level on a line of
+        // its own, with signed ones.
+        for signed in [false, true] {
+            let with = |input: &str| {
+                if signed {
+                    format!("{input}-1 0\n")
+                } else {
+                    input.to_string()
+                }
+            };
+
+            // The `-1 0` line is safe.
+            let extra = usize::from(signed);
+
+            let input = with("9223372036854775804 9223372036854775805 9223372036854775807\n");
+            assert_eq!(try_part1(&input), Ok(1 + extra), "{input:?}");
+            assert_eq!(try_part1_generic(&input), Ok(1 + extra), "{input:?}");
+            assert_eq!(try_part2(&input), Ok(1 + extra), "{input:?}");
+            assert_eq!(try_part2_generic(&input), Ok(1 + extra), "{input:?}");
+            assert_eq!(try_part2_state_machine(&input), Ok(1 + extra), "{input:?}");
+
+            // Going from the largest level down to 0 is never a small step up.
+            let input = with("9223372036854775806 9223372036854775807 0 1\n");
+            assert_eq!(try_part1(&input), Ok(extra), "{input:?}");
+            assert_eq!(try_part1_generic(&input), Ok(extra), "{input:?}");
+            assert_eq!(try_part2(&input), Ok(extra), "{input:?}");
+            assert_eq!(try_part2_generic(&input), Ok(extra), "{input:?}");
+            assert_eq!(try_part2_state_machine(&input), Ok(extra), "{input:?}");
+
+            // Levels past `i64::MAX` are too large either way.
+            let input = with("18446744073709551614 18446744073709551615 0 1\n");
+            let err = Err(Error::new(1, 1, ErrorKind::NumberOverflow));
+            assert_eq!(try_part1(&input), err, "{input:?}");
+            assert_eq!(try_part1_generic(&input), err, "{input:?}");
+            assert_eq!(try_part2(&input), err, "{input:?}");
+            assert_eq!(try_part2_generic(&input), err, "{input:?}");
+            assert_eq!(try_part2_state_machine(&input), err, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_negative_levels() {
+        assert_eq!(
+            try_part2_state_machine("1 --2\n"),
+            Err(Error::new(1, 4, ErrorKind::ExpectedNumber))
+        );
+        assert_eq!(
+            try_part2_state_machine("1 2-\n"),
+            Err(Error::new(1, 4, ErrorKind::ExpectedSeparator))
+        );
+        assert_eq!(
+            try_part2_state_machine("1 - 2\n"),
+            Err(Error::new(1, 4, ErrorKind::ExpectedNumber))
+        );
+    }
 }
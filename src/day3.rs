@@ -1,98 +1,345 @@
+use std::{
+    fmt,
+    io::{self, Read},
+    num::NonZeroUsize,
+    ops::Range,
+    thread,
+};
+
 use aoc_runner_derive::aoc;
-use atoi::FromRadix10;
+use atoi::{FromRadix10, FromRadix10Checked};
 use regex::bytes::Regex;
 
-#[aoc(day3, part1)]
-fn part1(input: &str) -> usize {
-    let input = input.as_bytes();
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Mul(usize, usize),
+    Do,
+    Dont,
+}
 
-    input
-        .windows(4)
-        .enumerate()
-        .filter(|(_, window)| window == b"mul(")
-        .map(|(i, _)| {
-            let i = i + 4;
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Mul(a, b) => write!(f, "mul({a},{b})"),
+            Instruction::Do => write!(f, "do()"),
+            Instruction::Dont => write!(f, "don't()"),
+        }
+    }
+}
 
-            let (a, r) = usize::from_radix_10(&input[i..]);
+/// An instruction found in the corrupted memory, with the offset of its first byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub offset: usize,
+    pub instruction: Instruction,
+}
 
-            let i = i + r;
+/// How `mul` operands are read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Operands {
+    /// One to three digits, as the puzzle specifies.
+    #[default]
+    Strict,
+    /// Any number of digits, including none, as long as the value fits in a `usize`.
+    Lenient,
+}
 
-            if input[i] != b',' {
-                return 0;
+/// Parses the operand at the start of `args`, returning it and its length in bytes.
+fn parse_operand(args: &[u8], operands: Operands) -> Option<(usize, usize)> {
+    match operands {
+        Operands::Strict => {
+            let len = args
+                .iter()
+                .take(4)
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            if !(1..=3).contains(&len) {
+                return None;
             }
+            Some((usize::from_radix_10(&args[..len]).0, len))
+        }
+        Operands::Lenient => match usize::from_radix_10_checked(args) {
+            (Some(n), len) => Some((n, len)),
+            (None, _) => None,
+        },
+    }
+}
 
-            let i = i + 1;
+/// Parses the `a,b)` following a `mul(`, returning the operands and the length of the text read.
+fn parse_mul_args(args: &[u8], operands: Operands) -> Option<(usize, usize, usize)> {
+    let (a, r) = parse_operand(args, operands)?;
 
-            let (b, r) = usize::from_radix_10(&input[i..]);
-            let i = i + r;
+    let i = r;
 
-            if input[i] != b')' {
-                return 0;
-            }
-            a * b
-        })
-        .sum()
-}
+    if args.get(i) != Some(&b',') {
+        return None;
+    }
 
-#[aoc(day3, part1, Memmem)]
-fn part1_memmem(input: &str) -> usize {
-    let input = input.as_bytes();
+    let i = i + 1;
 
-    memchr::memmem::find_iter(input, "mul(")
-        .map(|i| {
-            let i = i + 4;
+    let (b, r) = parse_operand(&args[i..], operands)?;
+    let i = i + r;
+
+    if args.get(i) != Some(&b')') {
+        return None;
+    }
 
-            let (a, r) = usize::from_radix_10(&input[i..]);
+    Some((a, b, i + 1))
+}
+
+/// Reads the instruction whose name ends just before the `(` at `open`, returning it with the
+/// offsets of its first byte and of the byte after it.
+///
+/// This is the only place that knows the syntax of instructions.
+fn instruction_at(
+    input: &[u8],
+    open: usize,
+    operands: Operands,
+) -> Option<(usize, Instruction, usize)> {
+    let name = &input[..open];
+    let args = &input[open + 1..];
+
+    if name.ends_with(b"mul") {
+        let (a, b, len) = parse_mul_args(args, operands)?;
+        Some((open - 3, Instruction::Mul(a, b), open + 1 + len))
+    } else if name.ends_with(b"don't") && args.starts_with(b")") {
+        Some((open - 5, Instruction::Dont, open + 2))
+    } else if name.ends_with(b"do") && args.starts_with(b")") {
+        Some((open - 2, Instruction::Do, open + 2))
+    } else {
+        None
+    }
+}
 
-            let i = i + r;
+/// Iterator over the instructions in corrupted memory, created by [`tokens`].
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    input: &'a [u8],
+    pos: usize,
+    /// Only a `(` before this offset can start an instruction's arguments.
+    end: usize,
+    operands: Operands,
+}
 
-            if input[i] != b',' {
-                return 0;
+impl Iterator for Tokens<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        // The last instruction can end past `end`.
+        let rest = |pos| self.input.get(pos..self.end).unwrap_or_default();
+        while let Some(open) = memchr::memchr(b'(', rest(self.pos)) {
+            let open = self.pos + open;
+
+            if let Some((offset, instruction, end)) =
+                instruction_at(self.input, open, self.operands)
+            {
+                self.pos = end;
+                return Some(Token {
+                    offset,
+                    instruction,
+                });
             }
 
-            let i = i + 1;
+            self.pos = open + 1;
+        }
 
-            let (b, r) = usize::from_radix_10(&input[i..]);
-            let i = i + r;
+        self.pos = self.end;
+        None
+    }
+}
 
-            if input[i] != b')' {
-                return 0;
-            }
-            a * b
-        })
-        .sum()
+/// Scans corrupted memory for instructions, skipping everything else.
+pub fn tokens(input: &[u8]) -> Tokens<'_> {
+    tokens_with(input, Operands::Strict)
 }
 
-#[aoc(day3, part1, MemchrIter)]
-fn part1_memchr_iter(input: &str) -> usize {
-    let input = input.as_bytes();
+/// Like [`tokens`], reading `mul` operands as `operands` says.
+pub fn tokens_with(input: &[u8], operands: Operands) -> Tokens<'_> {
+    tokens_in(input, 0..input.len(), operands)
+}
 
-    memchr::memchr_iter(b'(', input)
-        .map(|i| {
-            if &input[i - 3..i] != b"mul" {
-                return 0;
-            }
+/// The instructions whose `(` lies in `range`. They may start before it and end after it.
+///
+/// No instruction contains a `(` other than its own, so every `(` reads the same instruction
+/// wherever the scan started, and scanning consecutive ranges finds the same instructions as
+/// scanning the whole input.
+fn tokens_in(input: &[u8], range: Range<usize>, operands: Operands) -> Tokens<'_> {
+    Tokens {
+        input,
+        pos: range.start,
+        end: range.end,
+        operands,
+    }
+}
+
+/// How instructions are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Semantics {
+    /// Whether `do()` and `don't()` switch the following `mul` instructions on and off.
+    pub conditionals: bool,
+}
+
+impl Semantics {
+    /// Every `mul` counts.
+    pub const PART1: Self = Self {
+        conditionals: false,
+    };
+
+    /// Only `mul` instructions enabled by the latest `do()` or `don't()` count.
+    pub const PART2: Self = Self { conditionals: true };
+}
 
-            let i = i + 1;
+/// Executes instructions one at a time, keeping the sum of the products of enabled `mul`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interpreter {
+    semantics: Semantics,
+    enabled: bool,
+    total: usize,
+}
 
-            let (a, r) = usize::from_radix_10(&input[i..]);
+impl Interpreter {
+    pub fn new(semantics: Semantics) -> Self {
+        Self::with_enabled(semantics, true)
+    }
 
-            let i = i + r;
+    /// An interpreter that starts with `mul` instructions switched on or off.
+    pub fn with_enabled(semantics: Semantics, enabled: bool) -> Self {
+        Self {
+            semantics,
+            enabled,
+            total: 0,
+        }
+    }
 
-            if input[i] != b',' {
-                return 0;
+    pub fn execute(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Mul(a, b) => {
+                // Lenient operands can be arbitrarily large, so wrap rather than panic.
+                if self.enabled {
+                    self.total = self.total.wrapping_add(a.wrapping_mul(b));
+                }
+            }
+            Instruction::Do => {
+                if self.semantics.conditionals {
+                    self.enabled = true;
+                }
             }
+            Instruction::Dont => {
+                if self.semantics.conditionals {
+                    self.enabled = false;
+                }
+            }
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+/// The instructions of some corrupted memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub tokens: Vec<Token>,
+}
+
+impl Program {
+    pub fn parse(input: &str) -> Self {
+        Self::parse_with(input, Operands::Strict)
+    }
+
+    pub fn parse_with(input: &str, operands: Operands) -> Self {
+        Self {
+            tokens: tokens_with(input.as_bytes(), operands).collect(),
+        }
+    }
+
+    pub fn evaluate(&self, semantics: Semantics) -> usize {
+        let mut interpreter = Interpreter::new(semantics);
+        for token in &self.tokens {
+            interpreter.execute(token.instruction);
+        }
+        interpreter.total()
+    }
+}
 
-            let i = i + 1;
+/// An executed instruction, with the interpreter's state right after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub token: Token,
+    pub enabled: bool,
+    pub total: usize,
+}
 
-            let (b, r) = usize::from_radix_10(&input[i..]);
-            let i = i + r;
+impl Step {
+    /// The step as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"offset":{},"instruction":"{}","enabled":{},"total":{}}}"#,
+            self.token.offset, self.token.instruction, self.enabled, self.total
+        )
+    }
+}
 
-            if input[i] != b')' {
-                return 0;
+/// Every instruction the solvers see in `input`, in order, as evaluated with `semantics`.
+pub fn trace(input: &str, semantics: Semantics) -> Vec<Step> {
+    let mut interpreter = Interpreter::new(semantics);
+    Program::parse(input)
+        .tokens
+        .into_iter()
+        .map(|token| {
+            interpreter.execute(token.instruction);
+            Step {
+                token,
+                enabled: interpreter.enabled(),
+                total: interpreter.total(),
             }
-            a * b
         })
+        .collect()
+}
+
+pub fn try_part1(input: &str) -> Result<usize, Error> {
+    Ok(Program::parse(input).evaluate(Semantics::PART1))
+}
+
+#[aoc(day3, part1)]
+fn part1(input: &str) -> usize {
+    try_part1(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+pub fn try_part1_lenient(input: &str) -> Result<usize, Error> {
+    Ok(Program::parse_with(input, Operands::Lenient).evaluate(Semantics::PART1))
+}
+
+#[aoc(day3, part1, Lenient)]
+fn part1_lenient(input: &str) -> usize {
+    try_part1_lenient(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+#[aoc(day3, part1, Memmem)]
+fn part1_memmem(input: &str) -> usize {
+    let input = input.as_bytes();
+
+    memchr::memmem::find_iter(input, "mul(")
+        .filter_map(|i| parse_mul_args(&input[i + 4..], Operands::Strict))
+        .map(|(a, b, _)| a * b)
+        .sum()
+}
+
+#[aoc(day3, part1, MemchrIter)]
+fn part1_memchr_iter(input: &str) -> usize {
+    let input = input.as_bytes();
+
+    memchr::memchr_iter(b'(', input)
+        .filter(|&i| input[..i].ends_with(b"mul"))
+        .filter_map(|i| parse_mul_args(&input[i + 1..], Operands::Strict))
+        .map(|(a, b, _)| a * b)
         .sum()
 }
 
@@ -103,81 +350,165 @@ fn part1_regex(input: &str) -> usize {
     let re = Regex::new(r"mul\(").unwrap();
 
     re.find_iter(input)
-        .map(|i| {
-            let i = i.end();
-
-            let (a, r) = usize::from_radix_10(&input[i..]);
+        .filter_map(|m| parse_mul_args(&input[m.end()..], Operands::Strict))
+        .map(|(a, b, _)| a * b)
+        .sum()
+}
 
-            let i = i + r;
+pub fn try_part2(input: &str) -> Result<usize, Error> {
+    Ok(Program::parse(input).evaluate(Semantics::PART2))
+}
 
-            if input[i] != b',' {
-                return 0;
-            }
+#[aoc(day3, part2)]
+fn part2(input: &str) -> usize {
+    try_part2(input).unwrap_or_else(|err| panic!("{err}"))
+}
 
-            let i = i + 1;
+pub fn try_part2_lenient(input: &str) -> Result<usize, Error> {
+    Ok(Program::parse_with(input, Operands::Lenient).evaluate(Semantics::PART2))
+}
 
-            let (b, r) = usize::from_radix_10(&input[i..]);
-            let i = i + r;
+#[aoc(day3, part2, Lenient)]
+fn part2_lenient(input: &str) -> usize {
+    try_part2_lenient(input).unwrap_or_else(|err| panic!("{err}"))
+}
 
-            if input[i] != b')' {
-                return 0;
-            }
-            a * b
-        })
-        .sum()
+/// The effect of a chunk of memory, for both states it can be entered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Summary {
+    /// The sum of the enabled products, indexed by whether the chunk starts enabled.
+    totals: [usize; 2],
+    /// Whether `mul` instructions are enabled after the chunk, indexed likewise.
+    enabled_after: [bool; 2],
 }
 
-#[aoc(day3, part2)]
-fn part2(input: &str) -> usize {
-    let input = input.as_bytes();
+impl Summary {
+    /// The summary of no memory at all.
+    const EMPTY: Self = Self {
+        totals: [0, 0],
+        enabled_after: [false, true],
+    };
+
+    fn of(input: &[u8], range: Range<usize>, semantics: Semantics) -> Self {
+        let mut interpreters =
+            [false, true].map(|enabled| Interpreter::with_enabled(semantics, enabled));
+        for token in tokens_in(input, range, Operands::Strict) {
+            for interpreter in &mut interpreters {
+                interpreter.execute(token.instruction);
+            }
+        }
 
-    let mut enabled = true;
+        Self {
+            totals: interpreters.map(|interpreter| interpreter.total()),
+            enabled_after: interpreters.map(|interpreter| interpreter.enabled()),
+        }
+    }
 
-    input
-        .iter()
-        .enumerate()
-        .filter(|(_, &c)| c == b'(')
-        .map(|(i, _)| {
-            if enabled {
-                if &input[i - 3..i] == b"mul" {
-                    let i = i + 1;
+    /// The summary of `self` followed by `next`. This is associative, so chunks can be
+    /// summarized independently and merged in order.
+    fn then(self, next: Self) -> Self {
+        let mut merged = Self::EMPTY;
+        for start in 0..2 {
+            let middle = self.enabled_after[start] as usize;
+            merged.totals[start] = self.totals[start].wrapping_add(next.totals[middle]);
+            merged.enabled_after[start] = next.enabled_after[middle];
+        }
+        merged
+    }
+}
 
-                    let (a, r) = usize::from_radix_10(&input[i..]);
+/// Evaluates `input` like [`Program::evaluate`], splitting it into one chunk per thread.
+pub fn evaluate_parallel(input: &str, semantics: Semantics, threads: NonZeroUsize) -> usize {
+    let input = input.as_bytes();
+    let chunk_len = input.len().div_ceil(threads.get()).max(1);
+
+    let summaries = thread::scope(|scope| {
+        let handles = (0..input.len())
+            .step_by(chunk_len)
+            .map(|start| {
+                let range = start..input.len().min(start + chunk_len);
+                scope.spawn(move || Summary::of(input, range, semantics))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    summaries
+        .into_iter()
+        .fold(Summary::EMPTY, Summary::then)
+        .totals[1]
+}
 
-                    let i = i + r;
+#[aoc(day3, part2, Parallel)]
+fn part2_parallel(input: &str) -> usize {
+    let threads = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+    evaluate_parallel(input, Semantics::PART2, threads)
+}
 
-                    if input[i] != b',' {
-                        return 0;
-                    }
+/// How far before its `(` an instruction can start: `don't` is the longest name.
+const MAX_NAME_LEN: usize = b"don't".len();
 
-                    let i = i + 1;
+/// How far after its `(` an instruction needs to be read: three-digit operands, plus one more
+/// digit to reject longer ones.
+const MAX_ARGS_LEN: usize = b"999,9999".len();
 
-                    let (b, r) = usize::from_radix_10(&input[i..]);
-                    let i = i + r;
+/// Evaluates the memory read from `reader` for both parts, returning the part 1 and part 2
+/// sums. Only a fixed-size buffer is kept in memory, and `mul` operands are read strictly.
+pub fn evaluate_reader(reader: impl Read) -> io::Result<(usize, usize)> {
+    evaluate_reader_with(reader, 64 * 1024)
+}
 
-                    if input[i] != b')' {
-                        return 0;
-                    }
-                    a * b
-                } else if &input[i - 5..i + 2] == b"don't()" {
-                    enabled = false;
-                    0
-                } else {
-                    0
-                }
-            } else if &input[i - 2..i + 2] == b"do()" {
-                enabled = true;
-                0
-            } else {
-                0
+fn evaluate_reader_with(mut reader: impl Read, buffer_len: usize) -> io::Result<(usize, usize)> {
+    let mut part1 = Interpreter::new(Semantics::PART1);
+    let mut part2 = Interpreter::new(Semantics::PART2);
+
+    // `buffer[..start]` has been scanned, and is only kept so names that start before the next
+    // `(` can be read.
+    let mut buffer = Vec::with_capacity(MAX_NAME_LEN + buffer_len + MAX_ARGS_LEN);
+    let mut start = 0;
+
+    loop {
+        let len = buffer.len();
+        buffer.resize(len + buffer_len, 0);
+        let read = loop {
+            match reader.read(&mut buffer[len..]) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
             }
-        })
-        .sum()
+        };
+        buffer.truncate(len + read);
+        let done = read == 0;
+
+        // Leave any `(` whose arguments might not have been read yet for the next buffer.
+        let end = if done {
+            buffer.len()
+        } else {
+            buffer.len().saturating_sub(MAX_ARGS_LEN).max(start)
+        };
+        for token in tokens_in(&buffer, start..end, Operands::Strict) {
+            part1.execute(token.instruction);
+            part2.execute(token.instruction);
+        }
+
+        if done {
+            return Ok((part1.total(), part2.total()));
+        }
+
+        let scanned = end.saturating_sub(MAX_NAME_LEN);
+        buffer.drain(..scanned);
+        start = end - scanned;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{input::line_ending_variants, test_util::XorShift};
 
     #[test]
     fn part1_example() {
@@ -194,4 +525,221 @@ mod tests {
             48
         );
     }
+
+    #[test]
+    fn instructions_at_input_edges() {
+        assert_eq!(try_part1("mul(2,4)mul(12"), Ok(8));
+        assert_eq!(try_part2("(mul(2,4)don't(do()mul(3,"), Ok(8));
+    }
+
+    #[test]
+    fn tokenizer_offsets() {
+        let program = Program::parse("xdon't()mul(2,4)mul(3,7]do()mul(11,8)");
+        assert_eq!(
+            program.tokens,
+            [
+                Token {
+                    offset: 1,
+                    instruction: Instruction::Dont
+                },
+                Token {
+                    offset: 8,
+                    instruction: Instruction::Mul(2, 4)
+                },
+                Token {
+                    offset: 24,
+                    instruction: Instruction::Do
+                },
+                Token {
+                    offset: 28,
+                    instruction: Instruction::Mul(11, 8)
+                },
+            ]
+        );
+        assert_eq!(program.evaluate(Semantics::PART1), 96);
+        assert_eq!(program.evaluate(Semantics::PART2), 88);
+    }
+
+    #[test]
+    fn interpreter_state() {
+        let mut interpreter = Interpreter::new(Semantics::PART2);
+        interpreter.execute(Instruction::Mul(2, 3));
+        interpreter.execute(Instruction::Dont);
+        assert!(!interpreter.enabled());
+        interpreter.execute(Instruction::Mul(5, 5));
+        assert_eq!(interpreter.total(), 6);
+
+        let mut interpreter = Interpreter::new(Semantics::PART1);
+        interpreter.execute(Instruction::Dont);
+        assert!(interpreter.enabled());
+    }
+
+    #[test]
+    fn operand_lengths() {
+        let solvers = [part1, part1_memmem, part1_memchr_iter, part1_regex, part2];
+        for (input, strict, lenient) in [
+            ("mul(1234,5)", 0, 6170),
+            ("mul(5,1234)", 0, 6170),
+            ("mul(,5)", 0, 0),
+            ("mul(5,)", 0, 0),
+            ("mul(,)", 0, 0),
+            ("mul(999,1)mul(0,7)mul(1,999)", 1998, 1998),
+            ("mul(99999999999999999999,1)", 0, 0),
+        ] {
+            let input = format!("xmul(2,3){input}");
+            for part in solvers {
+                assert_eq!(part(&input), 6 + strict, "{input}");
+            }
+            assert_eq!(try_part1_lenient(&input), Ok(6 + lenient), "{input}");
+            assert_eq!(try_part2_lenient(&input), Ok(6 + lenient), "{input}");
+        }
+
+        assert_eq!(
+            tokens_with(b"mul(,5)", Operands::Lenient).collect::<Vec<_>>(),
+            [Token {
+                offset: 0,
+                instruction: Instruction::Mul(0, 5)
+            }]
+        );
+        assert_eq!(tokens(b"mul(,5)").count(), 0);
+    }
+
+    #[test]
+    fn line_endings() {
+        for input in line_ending_variants(
+            "xmul(2,4)&mul[3,7]!^don't()\n_mul(5,5)+mul(32,64](mul(11,8)\nundo()?mul(8,5))\n",
+        ) {
+            assert_eq!(try_part1(&input), Ok(161));
+            assert_eq!(try_part2(&input), Ok(48));
+        }
+    }
+
+    /// Random bytes, with enough instruction fragments mixed in to form some instructions.
+    fn random_memory(rng: &mut XorShift, fragments: u64) -> String {
+        const FRAGMENTS: [&[u8]; 10] = [
+            b"mul(", b"do()", b"don't()", b"mul", b"do", b"(", b",", b")", b"1", b"4242",
+        ];
+
+        let mut bytes = Vec::new();
+        for _ in 0..fragments {
+            match rng.below(3) {
+                0 => bytes.push(rng.next_u64() as u8),
+                1 => bytes.push(b'0' + rng.below(10) as u8),
+                _ => bytes.extend_from_slice(FRAGMENTS[rng.below(10) as usize]),
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[test]
+    fn random_bytes() {
+        let mut rng = XorShift::new(3);
+
+        for _ in 0..5000 {
+            let fragments = rng.below(16);
+            let input = random_memory(&mut rng, fragments);
+
+            let expected = part1(&input);
+            for part in [part1_memmem, part1_memchr_iter, part1_regex] {
+                assert_eq!(part(&input), expected, "{input:?}");
+            }
+
+            let enabled = part2(&input);
+            assert!(enabled <= expected, "{input:?}");
+            if !input.contains("don't()") {
+                assert_eq!(enabled, expected, "{input:?}");
+            }
+
+            try_part1_lenient(&input).unwrap();
+            try_part2_lenient(&input).unwrap();
+        }
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let mut rng = XorShift::new(16);
+
+        for _ in 0..500 {
+            let fragments = rng.below(40);
+            let input = random_memory(&mut rng, fragments);
+
+            let expected = [try_part1(&input).unwrap(), try_part2(&input).unwrap()];
+            for threads in 1..=12 {
+                let threads = NonZeroUsize::new(threads).unwrap();
+                assert_eq!(
+                    [Semantics::PART1, Semantics::PART2]
+                        .map(|semantics| evaluate_parallel(&input, semantics, threads)),
+                    expected,
+                    "{input:?} on {threads} threads"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_splits_instructions() {
+        let input = "mul(2,3)don't()mul(5,5)do()mul(7,1)";
+        for threads in 1..=input.len() + 1 {
+            let threads = NonZeroUsize::new(threads).unwrap();
+            assert_eq!(evaluate_parallel(input, Semantics::PART2, threads), 13);
+            assert_eq!(evaluate_parallel(input, Semantics::PART1, threads), 38);
+        }
+        assert_eq!(
+            evaluate_parallel("", Semantics::PART2, NonZeroUsize::MIN),
+            0
+        );
+    }
+
+    #[test]
+    fn reader_matches_sequential() {
+        let mut rng = XorShift::new(17);
+
+        for _ in 0..500 {
+            let fragments = rng.below(40);
+            let input = random_memory(&mut rng, fragments);
+
+            let expected = (try_part1(&input).unwrap(), try_part2(&input).unwrap());
+            for buffer_len in 1..=16 {
+                assert_eq!(
+                    evaluate_reader_with(input.as_bytes(), buffer_len).unwrap(),
+                    expected,
+                    "{input:?} with {buffer_len} byte buffers"
+                );
+            }
+            assert_eq!(evaluate_reader(input.as_bytes()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn reader_example() {
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+        assert_eq!(evaluate_reader(input.as_bytes()).unwrap(), (161, 48));
+        assert_eq!(
+            evaluate_reader_with(input.as_bytes(), 3).unwrap(),
+            (161, 48)
+        );
+    }
+
+    #[test]
+    fn trace_example() {
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+
+        let steps = trace(input, Semantics::PART2);
+        assert_eq!(
+            steps.iter().map(Step::to_json).collect::<Vec<_>>(),
+            [
+                r#"{"offset":1,"instruction":"mul(2,4)","enabled":true,"total":8}"#,
+                r#"{"offset":20,"instruction":"don't()","enabled":false,"total":8}"#,
+                r#"{"offset":28,"instruction":"mul(5,5)","enabled":false,"total":8}"#,
+                r#"{"offset":48,"instruction":"mul(11,8)","enabled":false,"total":8}"#,
+                r#"{"offset":59,"instruction":"do()","enabled":true,"total":8}"#,
+                r#"{"offset":64,"instruction":"mul(8,5)","enabled":true,"total":48}"#,
+            ]
+        );
+        assert_eq!(steps.last().unwrap().total, try_part2(input).unwrap());
+        assert_eq!(
+            trace(input, Semantics::PART1).last().unwrap().total,
+            try_part1(input).unwrap()
+        );
+    }
 }
@@ -1,131 +1,685 @@
+use std::{collections::HashSet, fmt};
+
 use aoc_runner_derive::aoc;
 
-fn index(input: &[u8], row: Option<usize>, col: Option<usize>, cols: usize) -> Option<u8> {
-    if let (Some(col), Some(row)) = (col, row) {
-        if col < cols {
-            input.get(row * cols + col).copied()
+use crate::{
+    grid::{check_rows, Direction, Grid, Point, Ragged, Region},
+    input::normalize_line_endings,
+    Error, ErrorKind,
+};
+
+/// Where a word was found: the cell of its first letter and the way it reads from there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordMatch {
+    pub start: Point,
+    pub direction: Direction,
+}
+
+/// Where [`count_word_in`] looks for words.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchArea {
+    /// The part of the grid words must lie in, or the whole grid.
+    pub region: Option<Region>,
+    /// Whether words running off an edge of the region carry on from the opposite edge, as on
+    /// a torus.
+    pub wrap: bool,
+}
+
+impl SearchArea {
+    /// The searched region, clipped to the grid.
+    fn bounds(&self, grid: &Grid<'_>) -> Region {
+        self.region
+            .map_or(grid.bounds(), |region| region.intersect(grid.bounds()))
+    }
+
+    /// The point `steps` steps from `start`, which must be in `bounds`, in `direction`.
+    fn step(
+        &self,
+        bounds: Region,
+        start: Point,
+        direction: Direction,
+        steps: usize,
+    ) -> Option<Point> {
+        if !self.wrap {
+            return start
+                .step(direction, steps as isize)
+                .filter(|&point| bounds.contains(point));
+        }
+
+        let wrap = |from: usize, origin: usize, size: usize, delta: isize| {
+            let offset = (from - origin) as isize + delta * steps as isize;
+            origin + offset.rem_euclid(size as isize) as usize
+        };
+        Some(Point::new(
+            wrap(start.row, bounds.top_left.row, bounds.height, direction.row),
+            wrap(start.col, bounds.top_left.col, bounds.width, direction.col),
+        ))
+    }
+
+    /// The cells of a match of a word of `len` letters found in this area, from its first
+    /// letter. A match starting outside the area has no cells.
+    pub fn cells(
+        &self,
+        grid: &Grid<'_>,
+        word_match: WordMatch,
+        len: usize,
+    ) -> impl Iterator<Item = Point> {
+        let area = *self;
+        let bounds = self.bounds(grid);
+        let len = if bounds.contains(word_match.start) {
+            len
         } else {
-            None
+            0
+        };
+        (0..len).filter_map(move |steps| {
+            area.step(bounds, word_match.start, word_match.direction, steps)
+        })
+    }
+}
+
+/// Finds every occurrence of `word` reading in one of `directions`, in row-major order of
+/// their first letters.
+///
+/// A palindrome reads the same both ways, so it is found twice when `directions` contains
+/// opposite directions. With `distinct`, each set of cells is only reported the first time it
+/// is found. An empty word is never found.
+pub fn count_word(
+    grid: &Grid<'_>,
+    word: &[u8],
+    directions: &[Direction],
+    distinct: bool,
+) -> Vec<WordMatch> {
+    count_word_in(grid, word, directions, distinct, SearchArea::default())
+}
+
+/// Like [`count_word`], but only finds words that lie in `area`.
+pub fn count_word_in(
+    grid: &Grid<'_>,
+    word: &[u8],
+    directions: &[Direction],
+    distinct: bool,
+    area: SearchArea,
+) -> Vec<WordMatch> {
+    let mut matches = Vec::new();
+    let mut seen = HashSet::new();
+
+    visit_words(grid, word, directions, area, |word_match| {
+        if distinct {
+            let mut cells = area.cells(grid, word_match, word.len()).collect::<Vec<_>>();
+            cells.sort_unstable();
+            cells.dedup();
+            if !seen.insert(cells) {
+                return;
+            }
         }
-    } else {
-        None
+        matches.push(word_match);
+    });
+
+    matches
+}
+
+/// How many words [`count_word_in`] finds without `distinct`, without collecting them.
+pub fn count_matches_in(
+    grid: &Grid<'_>,
+    word: &[u8],
+    directions: &[Direction],
+    area: SearchArea,
+) -> usize {
+    if !area.wrap && area.bounds(grid) == grid.bounds() {
+        return grid.count_word(word, directions);
     }
+
+    let mut count = 0;
+    visit_words(grid, word, directions, area, |_| count += 1);
+    count
+}
+
+/// Calls `visit` with every occurrence of `word` in `area`, in row-major order of their first
+/// letters, finding palindromes both ways.
+fn visit_words(
+    grid: &Grid<'_>,
+    word: &[u8],
+    directions: &[Direction],
+    area: SearchArea,
+    mut visit: impl FnMut(WordMatch),
+) {
+    let Some(&first) = word.first() else {
+        return;
+    };
+
+    let bounds = area.bounds(grid);
+    if !area.wrap && bounds == grid.bounds() {
+        // Words that can't wrap or leave a region can be read straight from the grid.
+        grid.find_word(word, directions, |start, direction| {
+            visit(WordMatch { start, direction })
+        });
+        return;
+    }
+
+    for start in bounds.points() {
+        if grid.get(start) != Some(first) {
+            continue;
+        }
+
+        for &direction in directions {
+            let found = word.iter().enumerate().rev().all(|(steps, &letter)| {
+                area.step(bounds, start, direction, steps)
+                    .and_then(|point| grid.get(point))
+                    == Some(letter)
+            });
+            if found {
+                visit(WordMatch { start, direction });
+            }
+        }
+    }
+}
+
+/// The word part 1 looks for, in every direction.
+pub const XMAS: &[u8] = b"XMAS";
+
+/// Every `XMAS` counted by part 1.
+pub fn find_xmas(grid: &Grid<'_>) -> Vec<WordMatch> {
+    count_word(grid, XMAS, &Direction::ALL, false)
+}
+
+pub fn try_part1(input: &str) -> Result<usize, Error> {
+    try_part1_with(input, Ragged::Reject)
+}
+
+/// Like [`try_part1`], handling rows of different widths as `ragged` says.
+pub fn try_part1_with(input: &str, ragged: Ragged) -> Result<usize, Error> {
+    let input = normalize_line_endings(input);
+    let input = check_rows(input.as_bytes(), ragged)?;
+    let grid = Grid::new(&input);
+
+    Ok(count_matches_in(
+        &grid,
+        XMAS,
+        &Direction::ALL,
+        SearchArea::default(),
+    ))
 }
 
 #[aoc(day4, part1)]
 pub fn part1(input: &str) -> usize {
-    let input = input.as_bytes();
-
-    let offsets: [(isize, isize); 8] = [
-        (-1, -1),
-        (-1, 0),
-        (-1, 1),
-        (0, -1),
-        (0, 1),
-        (1, -1),
-        (1, 0),
-        (1, 1),
-    ];
+    try_part1(input).unwrap_or_else(|err| panic!("{err}"))
+}
 
-    let mut total = 0;
+/// A small rectangular pattern to look for in a grid, where `None` matches any cell.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Template {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<u8>>,
+}
+
+impl Template {
+    /// Parses rows separated by `/`, where `.` matches any cell, e.g. `M.S/.A./M.S`.
+    pub fn parse(pattern: &str) -> Result<Self, Error> {
+        let rows = pattern.as_bytes().split(|&c| c == b'/').collect::<Vec<_>>();
+        let width = rows[0].len();
+
+        let mut cells = Vec::with_capacity(width * rows.len());
+        for (row, bytes) in rows.iter().enumerate() {
+            if bytes.len() != width {
+                return Err(Error::new(
+                    row + 1,
+                    bytes.len().min(width) + 1,
+                    ErrorKind::UnevenRows,
+                ));
+            }
+            cells.extend(bytes.iter().map(|&c| (c != b'.').then_some(c)));
+        }
+
+        Ok(Self {
+            width,
+            height: if width == 0 { 0 } else { rows.len() },
+            cells,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The cell at `point`, or `None` for a wildcard or a point outside the template.
+    pub fn get(&self, point: Point) -> Option<u8> {
+        if point.row >= self.height || point.col >= self.width {
+            return None;
+        }
+        self.cells[point.row * self.width + point.col]
+    }
+
+    /// The cells that aren't wildcards, with what they require.
+    fn required_cells(&self) -> Vec<(Point, u8)> {
+        (0..self.height)
+            .flat_map(|row| (0..self.width).map(move |col| Point::new(row, col)))
+            .filter_map(|point| Some((point, self.get(point)?)))
+            .collect()
+    }
 
-    let cols = input.iter().position(|&c| c == b'\n').unwrap() + 1;
-
-    let rows = input.len().div_ceil(cols);
-
-    for row in 0..rows {
-        for col in 0..cols - 1 {
-            if index(input, Some(row), Some(col), cols) == Some(b'X') {
-                for (row_offset, col_offset) in offsets {
-                    if index(
-                        input,
-                        row.checked_add_signed(3 * row_offset),
-                        col.checked_add_signed(3 * col_offset),
-                        cols,
-                    ) == Some(b'S')
-                    {
-                        if index(
-                            input,
-                            row.checked_add_signed(2 * row_offset),
-                            col.checked_add_signed(2 * col_offset),
-                            cols,
-                        ) == Some(b'A')
-                        {
-                            if index(
-                                input,
-                                row.checked_add_signed(row_offset),
-                                col.checked_add_signed(col_offset),
-                                cols,
-                            ) == Some(b'M')
-                            {
-                                total += 1;
-                            }
-                        }
+    /// The cells that aren't wildcards when the template is placed at `top_left`.
+    pub fn cells_at(&self, top_left: Point) -> impl Iterator<Item = Point> {
+        self.required_cells()
+            .into_iter()
+            .map(move |(point, _)| Point::new(top_left.row + point.row, top_left.col + point.col))
+    }
+
+    /// The template turned a quarter turn clockwise.
+    pub fn rotate(&self) -> Self {
+        let cells = (0..self.width)
+            .flat_map(|row| {
+                (0..self.height).map(move |col| (self.height - 1 - col) * self.width + row)
+            })
+            .map(|i| self.cells[i])
+            .collect();
+
+        Self {
+            width: self.height,
+            height: self.width,
+            cells,
+        }
+    }
+
+    /// The template mirrored left to right.
+    pub fn reflect(&self) -> Self {
+        let cells = self
+            .cells
+            .chunks(self.width.max(1))
+            .flat_map(|row| row.iter().rev().copied())
+            .collect();
+
+        Self { cells, ..*self }
+    }
+
+    /// Every distinct rotation and reflection of the template, starting with itself.
+    pub fn orientations(&self) -> Vec<Self> {
+        let mut orientations: Vec<Self> = Vec::with_capacity(8);
+        for mut template in [self.clone(), self.reflect()] {
+            for _ in 0..4 {
+                let next = template.rotate();
+                if !orientations.contains(&template) {
+                    orientations.push(template);
+                }
+                template = next;
+            }
+        }
+        orientations
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, cells) in self.cells.chunks(self.width.max(1)).enumerate() {
+            if row > 0 {
+                f.write_str("/")?;
+            }
+            for cell in cells {
+                write!(f, "{}", cell.unwrap_or(b'.') as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a template was found: the cell under its top left corner and which of the searched
+/// templates matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub top_left: Point,
+    pub template: usize,
+}
+
+/// Finds every placement of any of `templates` that lies within the grid, in row-major order of
+/// their top left corners. Pass [`Template::orientations`] to match rotations and reflections
+/// too. Empty templates are never found.
+pub fn match_template(grid: &Grid<'_>, templates: &[Template]) -> Vec<Placement> {
+    // Only the cells that aren't wildcards need checking, and the ones every template requires
+    // (like the middle `A` of all orientations of an X-MAS) only need checking once.
+    let mut required = templates
+        .iter()
+        .map(Template::required_cells)
+        .collect::<Vec<_>>();
+    let common = required.first().map_or_else(Vec::new, |first| {
+        first
+            .iter()
+            .copied()
+            .filter(|cell| required.iter().all(|cells| cells.contains(cell)))
+            .collect::<Vec<_>>()
+    });
+    for cells in &mut required {
+        cells.retain(|cell| !common.contains(cell));
+    }
+
+    // The last row and column a top left corner can be in for each template to fit.
+    let last = templates
+        .iter()
+        .map(|template| {
+            let row = grid.height().checked_sub(template.height)?;
+            let col = grid.width().checked_sub(template.width)?;
+            (template.width > 0).then_some((row, col))
+        })
+        .collect::<Vec<_>>();
+    let (Some(last_row), Some(last_col)) = (
+        last.iter().flatten().map(|&(row, _)| row).max(),
+        last.iter().flatten().map(|&(_, col)| col).max(),
+    ) else {
+        return Vec::new();
+    };
+
+    // The rows under the templates, fetched once for every row of top left corners. Rows past
+    // the bottom of the grid are empty, but only templates that fit are looked up in them.
+    let height = templates.iter().map(Template::height).max().unwrap_or(0);
+    let mut rows = Vec::with_capacity(height);
+
+    // Rules out the first `len` of the top left corners from column `left` on that don't have
+    // all of `cells`. Comparing whole runs of cells without branching on them is much faster
+    // than checking one corner at a time.
+    const CHUNK: usize = 64;
+    let check = |hits: &mut [bool; CHUNK],
+                 rows: &[&[u8]],
+                 left: usize,
+                 len: usize,
+                 cells: &[(Point, u8)]| {
+        for &(point, cell) in cells {
+            let from = left + point.col;
+            for (hit, &c) in hits.iter_mut().zip(&rows[point.row][from..from + len]) {
+                *hit &= c == cell;
+            }
+        }
+    };
+    let mut found = vec![[false; CHUNK]; templates.len()];
+    let mut placements = Vec::new();
+
+    for top in 0..=last_row {
+        rows.clear();
+        rows.extend((top..top + height).map(|row| grid.row_bytes(row)));
+
+        for left in (0..=last_col).step_by(CHUNK) {
+            // Every template contains the common cells, so they lie within the grid wherever
+            // the smallest template fits.
+            let len = CHUNK.min(last_col + 1 - left);
+            let mut common_hits = [true; CHUNK];
+            check(&mut common_hits, &rows, left, len, &common);
+
+            for (i, found) in found.iter_mut().enumerate() {
+                *found = [false; CHUNK];
+                if let Some((_, col)) = last[i].filter(|&(row, col)| top <= row && left <= col) {
+                    let len = len.min(col + 1 - left);
+                    found[..len].copy_from_slice(&common_hits[..len]);
+                    check(found, &rows, left, len, &required[i]);
+                }
+            }
+
+            // Placements are rare, so skipping the corners without any is worth the extra pass.
+            let mut any = [false; CHUNK];
+            for found in &found {
+                for (any, &found) in any.iter_mut().zip(found) {
+                    *any |= found;
+                }
+            }
+            for col in (0..len).filter(|&col| any[col]) {
+                for (i, found) in found.iter().enumerate() {
+                    if found[col] {
+                        placements.push(Placement {
+                            top_left: Point::new(top, left + col),
+                            template: i,
+                        });
                     }
                 }
             }
         }
     }
 
-    total
+    placements
+}
+
+/// The shape part 2 looks for, in every orientation.
+pub const X_MAS: &str = "M.S/.A./M.S";
+
+/// Every X-MAS counted by part 2, with the orientations of [`X_MAS`] the placements refer to.
+pub fn find_x_mas(grid: &Grid<'_>) -> (Vec<Template>, Vec<Placement>) {
+    let templates = Template::parse(X_MAS)
+        .expect("X_MAS is rectangular")
+        .orientations();
+    let placements = match_template(grid, &templates);
+    (templates, placements)
+}
+
+pub fn try_part2(input: &str) -> Result<usize, Error> {
+    try_part2_with(input, Ragged::Reject)
+}
+
+/// Like [`try_part2`], handling rows of different widths as `ragged` says.
+pub fn try_part2_with(input: &str, ragged: Ragged) -> Result<usize, Error> {
+    let input = normalize_line_endings(input);
+    let input = check_rows(input.as_bytes(), ragged)?;
+    let grid = Grid::new(&input);
+
+    Ok(find_x_mas(&grid).1.len())
 }
 
 #[aoc(day4, part2)]
 pub fn part2(input: &str) -> usize {
-    let input = input.as_bytes();
+    try_part2(input).unwrap_or_else(|err| panic!("{err}"))
+}
 
-    let mut total = 0;
+/// One bit per cell for each of a few letters, row by row, so 64 cells can be checked at once.
+struct Bitboard {
+    /// The number of `u64`s per row.
+    words: usize,
+    height: usize,
+    letters: Vec<Vec<u64>>,
+}
 
-    let cols = input.iter().position(|&c| c == b'\n').unwrap() + 1;
-
-    let rows = input.len().div_ceil(cols);
-
-    for row in 1..rows - 1 {
-        for col in 1..cols - 2 {
-            if index(input, Some(row), Some(col), cols) == Some(b'A') {
-                let top_left = index(
-                    input,
-                    row.checked_add_signed(-1),
-                    col.checked_add_signed(-1),
-                    cols,
-                );
-                let bottom_right = index(
-                    input,
-                    row.checked_add_signed(1),
-                    col.checked_add_signed(1),
-                    cols,
-                );
-                if (top_left == Some(b'M') && bottom_right == Some(b'S'))
-                    || (top_left == Some(b'S') && bottom_right == Some(b'M'))
-                {
-                    let top_right = index(
-                        input,
-                        row.checked_add_signed(-1),
-                        col.checked_add_signed(1),
-                        cols,
-                    );
-                    let bottom_left = index(
-                        input,
-                        row.checked_add_signed(1),
-                        col.checked_add_signed(-1),
-                        cols,
-                    );
-                    if (top_right == Some(b'M') && bottom_left == Some(b'S'))
-                        || (top_right == Some(b'S') && bottom_left == Some(b'M'))
-                    {
-                        total += 1;
-                    }
+impl Bitboard {
+    fn new(grid: &Grid<'_>, letters: &[u8]) -> Self {
+        let words = grid.width().div_ceil(64);
+        let mut boards = vec![vec![0; words * grid.height()]; letters.len()];
+
+        for (board, &letter) in boards.iter_mut().zip(letters) {
+            for (row, row_words) in board.chunks_mut(words.max(1)).enumerate() {
+                for (word, chunk) in row_words.iter_mut().zip(grid.row_bytes(row).chunks(64)) {
+                    *word = chunk.iter().enumerate().fold(0, |word, (bit, &cell)| {
+                        word | u64::from(cell == letter) << bit
+                    });
                 }
             }
         }
+
+        Self {
+            words,
+            height: grid.height(),
+            letters: boards,
+        }
+    }
+
+    /// Word `i` of `row` of the board of `letter`, moved so that bit `c` holds column
+    /// `c + cols`. `cols` must be less than 64 either way.
+    fn word(&self, letter: usize, row: usize, i: usize, cols: isize) -> u64 {
+        let bits = &self.letters[letter][row * self.words..][..self.words];
+        let shift = cols.unsigned_abs() as u32;
+
+        if cols >= 0 {
+            let carry = if shift == 0 {
+                0
+            } else {
+                bits.get(i + 1).map_or(0, |&next| next << (64 - shift))
+            };
+            (bits[i] >> shift) | carry
+        } else {
+            let carry = i
+                .checked_sub(1)
+                .map_or(0, |prev| bits[prev] >> (64 - shift));
+            (bits[i] << shift) | carry
+        }
+    }
+}
+
+pub fn try_part1_bitboard(input: &str) -> Result<usize, Error> {
+    let input = normalize_line_endings(input);
+    let input = check_rows(input.as_bytes(), Ragged::Reject)?;
+    let grid = Grid::new(&input);
+    let board = Bitboard::new(&grid, XMAS);
+
+    let mut total = 0;
+
+    for direction in Direction::ALL {
+        for row in 0..board.height {
+            // If the last letter is on the board, so are the ones between.
+            let reach = direction.row * (XMAS.len() as isize - 1);
+            if row
+                .checked_add_signed(reach)
+                .is_none_or(|last| last >= board.height)
+            {
+                continue;
+            }
+
+            for i in 0..board.words {
+                let starts = (0..XMAS.len()).fold(!0, |starts, letter| {
+                    let steps = letter as isize;
+                    let letter_row = row.wrapping_add_signed(direction.row * steps);
+                    starts & board.word(letter, letter_row, i, direction.col * steps)
+                });
+                total += starts.count_ones() as usize;
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+#[aoc(day4, part1, Bitboard)]
+pub fn part1_bitboard(input: &str) -> usize {
+    try_part1_bitboard(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+pub fn try_part2_bitboard(input: &str) -> Result<usize, Error> {
+    const M: usize = 0;
+    const A: usize = 1;
+    const S: usize = 2;
+
+    let input = normalize_line_endings(input);
+    let input = check_rows(input.as_bytes(), Ragged::Reject)?;
+    let grid = Grid::new(&input);
+    let board = Bitboard::new(&grid, b"MAS");
+
+    let mut total = 0;
+
+    for row in 1..board.height.saturating_sub(1) {
+        for i in 0..board.words {
+            let corner = |letter, row, cols| board.word(letter, row, i, cols);
+
+            // An `M` and an `S` at opposite ends of each diagonal through the `A`.
+            let down_right = (corner(M, row - 1, -1) & corner(S, row + 1, 1))
+                | (corner(S, row - 1, -1) & corner(M, row + 1, 1));
+            let down_left = (corner(M, row - 1, 1) & corner(S, row + 1, -1))
+                | (corner(S, row - 1, 1) & corner(M, row + 1, -1));
+
+            total += (corner(A, row, 0) & down_right & down_left).count_ones() as usize;
+        }
     }
 
-    total
+    Ok(total)
+}
+
+#[aoc(day4, part2, Bitboard)]
+pub fn part2_bitboard(input: &str) -> usize {
+    try_part2_bitboard(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// How many matches cover each cell of a grid, for showing what was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HitMap<'a> {
+    grid: Grid<'a>,
+    counts: Vec<usize>,
+}
+
+impl<'a> HitMap<'a> {
+    pub fn new(grid: Grid<'a>) -> Self {
+        Self {
+            grid,
+            counts: vec![0; grid.width() * grid.height()],
+        }
+    }
+
+    /// Counts a hit on `point`, if it is in the grid.
+    pub fn add(&mut self, point: Point) {
+        if self.grid.contains(point) {
+            self.counts[point.row * self.grid.width() + point.col] += 1;
+        }
+    }
+
+    /// Counts the cells of every match of a word of `len` letters found in `area`.
+    pub fn add_words(&mut self, area: SearchArea, len: usize, matches: &[WordMatch]) {
+        for &word_match in matches {
+            area.cells(&self.grid, word_match, len)
+                .for_each(|point| self.add(point));
+        }
+    }
+
+    /// Counts the cells of every placement of `templates`.
+    pub fn add_placements(&mut self, templates: &[Template], placements: &[Placement]) {
+        for placement in placements {
+            templates[placement.template]
+                .cells_at(placement.top_left)
+                .for_each(|point| self.add(point));
+        }
+    }
+
+    pub fn count(&self, point: Point) -> usize {
+        if self.grid.contains(point) {
+            self.counts[point.row * self.grid.width() + point.col]
+        } else {
+            0
+        }
+    }
+
+    fn render_with(&self, mut cell: impl FnMut(&mut String, u8, usize)) -> String {
+        let mut out = String::with_capacity((self.grid.width() + 1) * self.grid.height());
+        for row in self.grid.rows() {
+            for (point, byte) in row {
+                cell(&mut out, byte, self.count(point));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The grid with every cell that isn't part of a match replaced by `.`.
+    pub fn render(&self) -> String {
+        self.render_with(|out, byte, count| out.push(if count > 0 { byte as char } else { '.' }))
+    }
+
+    /// The whole grid for a terminal, with matched cells in bold red and the rest dimmed.
+    pub fn render_ansi(&self) -> String {
+        self.render_with(|out, byte, count| {
+            let style = if count > 0 { "1;31" } else { "2" };
+            out.push_str(&format!("\x1b[{style}m{}\x1b[0m", byte as char));
+        })
+    }
+
+    /// How many matches cover each cell: `.` for none, `1` to `9`, or `+` for more.
+    pub fn render_heatmap(&self) -> String {
+        self.render_with(|out, _, count| {
+            out.push(match count {
+                0 => '.',
+                1..=9 => char::from(b'0' + count as u8),
+                _ => '+',
+            })
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{input::line_ending_variants, test_util::XorShift};
+    use itertools::Itertools;
 
     #[test]
     fn part1_example() {
@@ -136,4 +690,438 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2("MMMSXXMASM\nMSAMXMSMSA\nAMXSXMAAMM\nMSAMASMSMX\nXMASAMXAMM\nXXAMMXXAMA\nSMSMSASXSS\nSAXAMASAAA\nMAMMMXMMMM\nMXMXAXMASX\n"), 9);
     }
+
+    #[test]
+    fn degenerate_grids() {
+        assert_eq!(try_part1(""), Ok(0));
+        assert_eq!(try_part1("XMASAMX"), Ok(2));
+        assert_eq!(try_part2("A"), Ok(0));
+    }
+
+    #[test]
+    fn line_endings() {
+        for input in line_ending_variants("MMMSXXMASM\nMSAMXMSMSA\nAMXSXMAAMM\nMSAMASMSMX\nXMASAMXAMM\nXXAMMXXAMA\nSMSMSASXSS\nSAXAMASAAA\nMAMMMXMMMM\nMXMXAXMASX\n") {
+            assert_eq!(try_part1(&input), Ok(18));
+            assert_eq!(try_part2(&input), Ok(9));
+            assert_eq!(try_part1_bitboard(&input), Ok(18));
+            assert_eq!(try_part2_bitboard(&input), Ok(9));
+        }
+    }
+
+    #[test]
+    fn word_matches() {
+        let grid = Grid::new(b"XMAS\nMM..\nA.A.\nS..S\n");
+        assert_eq!(
+            count_word(&grid, b"XMAS", &Direction::ALL, false),
+            [
+                WordMatch {
+                    start: Point::new(0, 0),
+                    direction: Direction::E
+                },
+                WordMatch {
+                    start: Point::new(0, 0),
+                    direction: Direction::SE
+                },
+                WordMatch {
+                    start: Point::new(0, 0),
+                    direction: Direction::S
+                },
+            ]
+        );
+        assert_eq!(count_word(&grid, b"XMAS", &[Direction::E], false).len(), 1);
+        assert_eq!(count_word(&grid, b"SAMX", &Direction::ALL, false).len(), 3);
+        assert!(count_word(&grid, b"", &Direction::ALL, false).is_empty());
+        assert!(count_word(&grid, b"XMASX", &Direction::ALL, false).is_empty());
+    }
+
+    #[test]
+    fn counting_matches_collected() {
+        let mut rng = XorShift::new(20);
+
+        for _ in 0..300 {
+            let width = 1 + rng.below(12) as usize;
+            let height = 1 + rng.below(12) as usize;
+            let input = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| b"XMAS"[rng.below(4) as usize] as char)
+                        .collect::<String>()
+                })
+                .join("\n");
+            let grid = Grid::new(input.as_bytes());
+            let area = SearchArea {
+                region: (rng.below(2) == 0).then(|| {
+                    let top_left = Point::new(rng.below(4) as usize, rng.below(4) as usize);
+                    Region::new(top_left, rng.below(10) as usize, rng.below(10) as usize)
+                }),
+                wrap: rng.below(2) == 0,
+            };
+
+            assert_eq!(
+                count_matches_in(&grid, XMAS, &Direction::ALL, area),
+                count_word_in(&grid, XMAS, &Direction::ALL, false, area).len(),
+                "{input:?} {area:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn palindromes() {
+        let grid = Grid::new(b"ABA\nB.B\nABA\n");
+        assert_eq!(count_word(&grid, b"ABA", &Direction::ALL, false).len(), 8);
+        assert_eq!(count_word(&grid, b"ABA", &Direction::ALL, true).len(), 4);
+        assert_eq!(
+            count_word(&grid, b"ABA", &Direction::ORTHOGONAL, true),
+            [
+                WordMatch {
+                    start: Point::new(0, 0),
+                    direction: Direction::E
+                },
+                WordMatch {
+                    start: Point::new(0, 0),
+                    direction: Direction::S
+                },
+                WordMatch {
+                    start: Point::new(0, 2),
+                    direction: Direction::S
+                },
+                WordMatch {
+                    start: Point::new(2, 0),
+                    direction: Direction::E
+                },
+            ]
+        );
+        assert_eq!(count_word(&grid, b"B", &Direction::ALL, false).len(), 32);
+        assert_eq!(count_word(&grid, b"B", &Direction::ALL, true).len(), 4);
+    }
+
+    #[test]
+    fn templates() {
+        let template = Template::parse("M.S/.A./M.S").unwrap();
+        assert_eq!(template.to_string(), "M.S/.A./M.S");
+        assert_eq!(template.rotate().to_string(), "M.M/.A./S.S");
+        assert_eq!(template.reflect().to_string(), "S.M/.A./S.M");
+        assert_eq!(
+            template
+                .orientations()
+                .iter()
+                .map(Template::to_string)
+                .collect::<Vec<_>>(),
+            ["M.S/.A./M.S", "M.M/.A./S.S", "S.M/.A./S.M", "S.S/.A./M.M"]
+        );
+
+        let template = Template::parse("AB./..C").unwrap();
+        assert_eq!((template.width(), template.height()), (3, 2));
+        assert_eq!(template.rotate().to_string(), ".A/.B/C.");
+        assert_eq!(template.orientations().len(), 8);
+        assert_eq!(template.get(Point::new(1, 2)), Some(b'C'));
+        assert_eq!(template.get(Point::new(1, 1)), None);
+        assert_eq!(template.get(Point::new(0, 3)), None);
+        assert_eq!(Template::parse("AB").unwrap().get(Point::new(1, 5)), None);
+
+        assert_eq!(
+            Template::parse("M.S/.A/M.S"),
+            Err(Error::new(2, 3, ErrorKind::UnevenRows))
+        );
+        assert_eq!(
+            Template::parse("M.S/.A./M.SS"),
+            Err(Error::new(3, 4, ErrorKind::UnevenRows))
+        );
+    }
+
+    #[test]
+    fn template_placements() {
+        let grid = Grid::new(b"XMAS\nAXMX\nSAMX\n");
+        let templates = [
+            Template::parse("MA").unwrap(),
+            Template::parse("X./.M").unwrap(),
+        ];
+        assert_eq!(
+            match_template(&grid, &templates),
+            [
+                Placement {
+                    top_left: Point::new(0, 1),
+                    template: 0
+                },
+                Placement {
+                    top_left: Point::new(1, 1),
+                    template: 1
+                },
+            ]
+        );
+        assert_eq!(
+            match_template(&grid, &Template::parse("XM").unwrap().orientations()).len(),
+            5
+        );
+        assert!(match_template(&grid, &[Template::parse("").unwrap()]).is_empty());
+        assert!(match_template(&grid, &[Template::parse("XMASX").unwrap()]).is_empty());
+
+        // Templates of different sizes on grids wider than the runs of cells checked at once.
+        let templates = ["M.S/.A./M.S", "XM", "A/A", "S..X", ".", ""]
+            .map(|pattern| Template::parse(pattern).unwrap());
+        let mut rng = XorShift::new(24);
+        for _ in 0..100 {
+            let width = 1 + rng.below(100) as usize;
+            let input = (0..1 + rng.below(6))
+                .map(|_| {
+                    (0..width)
+                        .map(|_| b"XMAS"[rng.below(4) as usize] as char)
+                        .collect::<String>()
+                })
+                .join("\n");
+            let grid = Grid::new(input.as_bytes());
+
+            let expected = grid
+                .points()
+                .flat_map(|top_left| {
+                    (0..templates.len()).map(move |template| Placement { top_left, template })
+                })
+                .filter(|&Placement { top_left, template }| {
+                    let template = &templates[template];
+                    template.width() > 0
+                        && top_left.row + template.height() <= grid.height()
+                        && top_left.col + template.width() <= grid.width()
+                        && template.cells_at(top_left).all(|point| {
+                            let offset =
+                                Point::new(point.row - top_left.row, point.col - top_left.col);
+                            grid.get(point) == template.get(offset)
+                        })
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(match_template(&grid, &templates), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn render_matches() {
+        let input = "MMMSXXMASM\nMSAMXMSMSA\nAMXSXMAAMM\nMSAMASMSMX\nXMASAMXAMM\nXXAMMXXAMA\nSMSMSASXSS\nSAXAMASAAA\nMAMMMXMMMM\nMXMXAXMASX\n";
+        let grid = Grid::new(input.as_bytes());
+
+        let mut hits = HitMap::new(grid);
+        hits.add_words(SearchArea::default(), XMAS.len(), &find_xmas(&grid));
+        assert_eq!(
+            hits.render(),
+            "....XXMAS.\n.SAMXMS...\n...S..A...\n..A.A.MS.X\nXMASAMX.MM\nX.....XA.A\nS.S.S.S.SS\n.A.A.A.A.A\n..M.M.M.MM\n.X.X.XMASX\n"
+        );
+        assert_eq!(
+            hits.render_heatmap(),
+            "....11111.\n.111111...\n...2..2...\n..1.1.11.2\n1212122.11\n1.....11.1\n1.1.1.3.12\n.1.2.1.2.1\n..2.2.1.11\n.1.2.31112\n"
+        );
+
+        let mut hits = HitMap::new(grid);
+        let (templates, placements) = find_x_mas(&grid);
+        hits.add_placements(&templates, &placements);
+        assert_eq!(
+            hits.render(),
+            ".M.S......\n..A..MSMS.\n.M.S.MAA..\n..A.ASMSM.\n.M.S.M....\n..........\nS.S.S.S.S.\n.A.A.A.A..\nM.M.M.M.M.\n..........\n"
+        );
+
+        // A word wrapping around an edge covers the cells on both sides of it.
+        let grid = Grid::new(b"ASXM");
+        let wrap = SearchArea {
+            region: None,
+            wrap: true,
+        };
+        let mut hits = HitMap::new(grid);
+        let matches = count_word_in(&grid, XMAS, &[Direction::E], false, wrap);
+        hits.add_words(wrap, XMAS.len(), &matches);
+        assert_eq!(hits.render(), "ASXM\n");
+
+        let mut hits = HitMap::new(Grid::new(b"XM\nAS"));
+        hits.add(Point::new(0, 1));
+        hits.add(Point::new(5, 5));
+        assert_eq!(
+            hits.render_ansi(),
+            "\x1b[2mX\x1b[0m\x1b[1;31mM\x1b[0m\n\x1b[2mA\x1b[0m\x1b[2mS\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn bitboard_matches_grid_search() {
+        let mut rng = XorShift::new(4);
+
+        for _ in 0..300 {
+            let width = 1 + rng.below(150) as usize;
+            let height = 1 + rng.below(8) as usize;
+            let input = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| b"XMAS."[rng.below(5) as usize] as char)
+                        .collect::<String>()
+                })
+                .join("\n");
+
+            assert_eq!(try_part1_bitboard(&input), try_part1(&input), "{input}");
+            assert_eq!(try_part2_bitboard(&input), try_part2(&input), "{input}");
+
+            let grid = Grid::new(input.as_bytes());
+            assert_eq!(try_part1(&input), Ok(find_xmas(&grid).len()), "{input}");
+            assert_eq!(try_part2(&input), Ok(find_x_mas(&grid).1.len()), "{input}");
+        }
+    }
+
+    #[test]
+    fn wrapping_words() {
+        let grid = Grid::new(b"ASXM\n.M..\n.X..\n....\n");
+        let wrap = SearchArea {
+            region: None,
+            wrap: true,
+        };
+
+        assert!(count_word(&grid, XMAS, &Direction::ALL, false).is_empty());
+        assert_eq!(
+            count_word_in(&grid, XMAS, &Direction::ALL, false, wrap),
+            [WordMatch {
+                start: Point::new(0, 2),
+                direction: Direction::E
+            }]
+        );
+        assert_eq!(
+            wrap.cells(
+                &grid,
+                WordMatch {
+                    start: Point::new(0, 2),
+                    direction: Direction::E
+                },
+                4
+            )
+            .collect::<Vec<_>>(),
+            [(0, 2), (0, 3), (0, 0), (0, 1)].map(|(row, col)| Point::new(row, col))
+        );
+
+        // Down from the `X` in the second column, wrapping past the bottom edge.
+        let grid = Grid::new(b".A\n.S\n.X\n.M\n");
+        assert_eq!(
+            count_word_in(&grid, XMAS, &[Direction::S], false, wrap),
+            [WordMatch {
+                start: Point::new(2, 1),
+                direction: Direction::S
+            }]
+        );
+
+        // On a 1x2 torus, `ABA` reads the same cells both ways.
+        let grid = Grid::new(b"AB");
+        let directions = [Direction::E, Direction::W];
+        assert_eq!(
+            count_word_in(&grid, b"ABA", &directions, false, wrap).len(),
+            2
+        );
+        assert_eq!(
+            count_word_in(&grid, b"ABA", &directions, true, wrap).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn words_in_region() {
+        let grid = Grid::new(b"XMAS.\n.....\nXMAS.\n.XMAS\n");
+        let area = |top_left, width, height, wrap| SearchArea {
+            region: Some(Region::new(top_left, width, height)),
+            wrap,
+        };
+
+        assert_eq!(count_word(&grid, XMAS, &Direction::ALL, false).len(), 3);
+        assert_eq!(
+            count_word_in(
+                &grid,
+                XMAS,
+                &Direction::ALL,
+                false,
+                area(Point::new(1, 0), 5, 3, false)
+            ),
+            [
+                WordMatch {
+                    start: Point::new(2, 0),
+                    direction: Direction::E
+                },
+                WordMatch {
+                    start: Point::new(3, 1),
+                    direction: Direction::E
+                },
+            ]
+        );
+        // The region cuts off the last letter of the bottom `XMAS`...
+        assert_eq!(
+            count_word_in(
+                &grid,
+                XMAS,
+                &Direction::ALL,
+                false,
+                area(Point::new(2, 0), 4, 2, false)
+            )
+            .len(),
+            1
+        );
+        // ...and wrapping within the region reads the `.` at its left edge instead.
+        assert_eq!(
+            count_word_in(
+                &grid,
+                XMAS,
+                &Direction::ALL,
+                false,
+                area(Point::new(2, 0), 4, 2, true)
+            )
+            .len(),
+            1
+        );
+        // Regions are clipped to the grid.
+        assert_eq!(
+            count_word_in(
+                &grid,
+                XMAS,
+                &Direction::ALL,
+                false,
+                area(Point::new(2, 1), 10, 10, false)
+            )
+            .len(),
+            1
+        );
+        for wrap in [false, true] {
+            for top_left in [Point::new(usize::MAX, 0), Point::new(0, usize::MAX)] {
+                assert!(count_word_in(
+                    &grid,
+                    XMAS,
+                    &Direction::ALL,
+                    false,
+                    area(top_left, 2, 2, wrap)
+                )
+                .is_empty());
+            }
+        }
+
+        // A match starting outside the region has no cells in it, wrapping or not.
+        let word_match = WordMatch {
+            start: Point::new(0, 1),
+            direction: Direction::E,
+        };
+        let grid = Grid::new(b"XMAS");
+        for region in [
+            Region::new(Point::new(5, 5), 2, 2),
+            Region::new(Point::new(0, 2), 2, 1),
+        ] {
+            for wrap in [false, true] {
+                let area = SearchArea {
+                    region: Some(region),
+                    wrap,
+                };
+                assert_eq!(area.cells(&grid, word_match, 4).count(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn ragged_grids() {
+        let input = "XMASX\nMM\nAXA\nSMAS\n";
+
+        let err = Error::new(2, 3, ErrorKind::UnevenRows);
+        assert_eq!(try_part1(input), Err(err));
+        assert_eq!(try_part2(input), Err(err));
+        assert_eq!(try_part1_bitboard(input), Err(err));
+        assert_eq!(try_part2_bitboard(input), Err(err));
+
+        // Along the first row, down the first column and diagonally from the top left corner.
+        assert_eq!(try_part1_with(input, Ragged::Pad), Ok(3));
+        assert_eq!(try_part2_with("M.S\nA\nM.S", Ragged::Pad), Ok(0));
+        assert_eq!(try_part2_with("M.S\n.A\nM.S", Ragged::Pad), Ok(1));
+    }
 }
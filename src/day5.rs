@@ -1,46 +1,365 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
 use aoc_runner_derive::aoc;
-use atoi::FromRadix10;
+use atoi::FromRadix10Checked;
+use itertools::Itertools;
 use nom::{
-    bytes::complete::{tag, take},
-    character::complete::{digit1, line_ending, not_line_ending},
-    combinator::{map, opt, value},
-    error::{Error, ErrorKind, ParseError},
-    multi::fold_many1,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{digit1, line_ending},
+    combinator::{cut, eof, not, opt, value},
+    error::{self, ParseError},
+    multi::many0_count,
     sequence::{preceded, separated_pair, terminated},
     Err, IResult,
 };
 
+use crate::{input::normalize_line_endings, Error, ErrorKind};
+
+/// The number `tens` and `ones` spell, if they are digits. Every page of the canonical puzzle
+/// input has two digits, which the parsers read directly before falling back to nom.
+fn two_digits(tens: u8, ones: u8) -> Option<usize> {
+    (tens.is_ascii_digit() && ones.is_ascii_digit())
+        .then(|| usize::from(tens - b'0') * 10 + usize::from(ones - b'0'))
+}
+
+/// Fails with nom's `TooLarge` when the page number doesn't fit in a `usize`.
 fn parse_num(i: &[u8]) -> IResult<&[u8], usize> {
-    map(digit1, |digit_str: &[u8]| usize::from_radix_10(digit_str).0)(i)
+    if let [tens, ones, rest @ ..] = i {
+        if let Some(num) = two_digits(*tens, *ones) {
+            if !rest.first().is_some_and(u8::is_ascii_digit) {
+                return Ok((rest, num));
+            }
+        }
+    }
+
+    let (rest, digit_str) = digit1(i)?;
+    match usize::from_radix_10_checked(digit_str) {
+        (Some(num), _) => Ok((rest, num)),
+        (None, _) => Err(Err::Failure(error::Error::new(
+            i,
+            error::ErrorKind::TooLarge,
+        ))),
+    }
+}
+
+/// A growable set of bits, used for the rule matrix of a [`RuleSet`] whose pages don't fit in a
+/// `u128`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn insert(&mut self, bit: usize) {
+        let word = bit / 64;
+        if word >= self.0.len() {
+            self.0.resize(word + 1, 0);
+        }
+        self.0[word] |= 1 << (bit % 64);
+    }
+
+    fn contains(&self, bit: usize) -> bool {
+        self.0
+            .get(bit / 64)
+            .is_some_and(|word| word & (1 << (bit % 64)) != 0)
+    }
+
+    fn intersects(&self, other: &BitSet) -> bool {
+        self.0.iter().zip(&other.0).any(|(a, b)| a & b != 0)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Repr {
+    /// Every page is below 128, so row `before` is the mask of pages that must come after it.
+    Small(Box<[u128; 128]>),
+    /// Pages are mapped to dense IDs, and row `ids[before]` holds the IDs of the pages that must
+    /// come after it.
+    Large {
+        ids: HashMap<usize, usize>,
+        pages: Vec<usize>,
+        rows: Vec<BitSet>,
+    },
+}
+
+/// The page ordering rules. A rule `before|after` means that if both pages are in an update,
+/// `before` must be printed at some point before `after`.
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    repr: Repr,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        Self {
+            repr: Repr::Small(Box::new([0; 128])),
+        }
+    }
+
+    /// Adds the rule `before|after`, switching to the dense ID representation the first time a
+    /// page does not fit in the bitmask.
+    pub fn insert(&mut self, before: usize, after: usize) {
+        match &mut self.repr {
+            Repr::Small(rules) if before < 128 && after < 128 => rules[before] |= 1 << after,
+            Repr::Small(_) => {
+                let rules = self.iter().collect::<Vec<_>>();
+                self.repr = Repr::Large {
+                    ids: HashMap::new(),
+                    pages: Vec::new(),
+                    rows: Vec::new(),
+                };
+                for (b, a) in rules {
+                    self.insert(b, a);
+                }
+                self.insert(before, after);
+            }
+            Repr::Large { ids, pages, rows } => {
+                let mut id = |page| {
+                    *ids.entry(page).or_insert_with(|| {
+                        pages.push(page);
+                        rows.push(BitSet::default());
+                        pages.len() - 1
+                    })
+                };
+                let before = id(before);
+                let after = id(after);
+                rows[before].insert(after);
+            }
+        }
+    }
+
+    /// Returns whether the rule `before|after` is in the set.
+    pub fn contains(&self, before: usize, after: usize) -> bool {
+        match &self.repr {
+            Repr::Small(rules) => before < 128 && after < 128 && rules[before] & (1 << after) != 0,
+            Repr::Large { ids, rows, .. } => match (ids.get(&before), ids.get(&after)) {
+                (Some(&before), Some(&after)) => rows[before].contains(after),
+                _ => false,
+            },
+        }
+    }
+
+    /// Iterates over every rule as `(before, after)`, sorted.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let rules: Box<dyn Iterator<Item = (usize, usize)>> = match &self.repr {
+            Repr::Small(rules) => Box::new(rules.iter().enumerate().flat_map(|(before, &row)| {
+                (0..128)
+                    .filter(move |after| row & (1 << after) != 0)
+                    .map(move |after| (before, after))
+            })),
+            Repr::Large { pages, rows, .. } => {
+                let mut rules = rows
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(before, row)| {
+                        row.iter().map(move |after| (pages[before], pages[after]))
+                    })
+                    .collect::<Vec<_>>();
+                rules.sort_unstable();
+                Box::new(rules.into_iter())
+            }
+        };
+        rules
+    }
+
+    /// Finds the rules that can never be satisfied together, whatever the update: self-rules such
+    /// as `5|5`, reported as a one-page cycle, and pairs of opposite rules such as `47|53` and
+    /// `53|47`, reported as a two-page cycle.
+    pub fn contradictions(&self) -> Vec<CycleError> {
+        self.iter()
+            .filter(|&(before, after)| before <= after && self.contains(after, before))
+            .map(|(before, after)| CycleError {
+                cycle: if before == after {
+                    vec![before]
+                } else {
+                    vec![before, after]
+                },
+            })
+            .collect()
+    }
+
+    /// Puts `update` in the only valid order when there is a rule between every pair of its
+    /// pages, as in the puzzle input, and returns whether any page moved. Each page then goes as
+    /// many places from the end as there are pages it must come before, which is quick to count
+    /// with a bitmask.
+    ///
+    /// Returns `None`, leaving `update` as it was, for any other update.
+    fn reorder_total(&self, update: &mut [usize]) -> Option<bool> {
+        let Repr::Small(rules) = &self.repr else {
+            return None;
+        };
+
+        let mut pages = 0u128;
+        for &page in update.iter() {
+            if page >= 128 || pages & (1 << page) != 0 {
+                return None;
+            }
+            pages |= 1 << page;
+        }
+
+        // The pages are distinct and below 128, so there are at most 128 places to fill.
+        let mut order = [0; 128];
+        let order = &mut order[..update.len()];
+        let mut filled = 0u128;
+        for &page in update.iter() {
+            let after = (rules[page] & pages).count_ones() as usize;
+            let place = update.len().checked_sub(after + 1)?;
+            if filled & (1 << place) != 0 {
+                return None;
+            }
+            filled |= 1 << place;
+            order[place] = page;
+        }
+
+        // The counts only add up to a valid order if no rule goes against it.
+        let mut seen = 0;
+        if !order
+            .iter()
+            .all(|&page| rules[page] & (1 << page) == 0 && visit_small(rules, &mut seen, page))
+        {
+            return None;
+        }
+
+        let moved = order != update;
+        update.copy_from_slice(order);
+        Some(moved)
+    }
+
+    /// The rules between the pages of `update`, as a row of `update.len().div_ceil(64)` words
+    /// per page: bit `j` of the row of page `i` is set when `update[i]` must come before
+    /// `update[j]`.
+    fn rules_between(&self, update: &[usize]) -> Vec<u64> {
+        let words = update.len().div_ceil(64);
+        let mut matrix = vec![0; update.len() * words];
+        let mut set = |i: usize, j: usize| matrix[i * words + j / 64] |= 1 << (j % 64);
+
+        match &self.repr {
+            Repr::Small(rules) => {
+                for (i, &before) in update.iter().enumerate() {
+                    let Some(&row) = rules.get(before) else {
+                        continue;
+                    };
+                    for (j, &after) in update.iter().enumerate() {
+                        if after < 128 && row & (1 << after) != 0 {
+                            set(i, j);
+                        }
+                    }
+                }
+            }
+            Repr::Large { ids, rows, .. } => {
+                let ids = update.iter().map(|page| ids.get(page)).collect::<Vec<_>>();
+                for (i, before) in ids.iter().enumerate() {
+                    let Some(&before) = before else {
+                        continue;
+                    };
+                    for (j, after) in ids.iter().enumerate() {
+                        if after.is_some_and(|&after| rows[before].contains(after)) {
+                            set(i, j);
+                        }
+                    }
+                }
+            }
+        }
+
+        matrix
+    }
+
+    /// Checks that the rules restricted to the pages of `update` have no cycle, so that the
+    /// update can be put in a valid order.
+    pub fn check_update(&self, update: &[usize]) -> Result<(), CycleError> {
+        topological_order(self, update).map(|_| ())
+    }
+}
+
+/// Marks `page` as seen in `seen`, the pages of an update checked so far against the rules of
+/// [`Repr::Small`]. Returns `false` if a page seen earlier must come after `page`.
+fn visit_small(rules: &[u128; 128], seen: &mut u128, page: usize) -> bool {
+    // Pages that don't fit in the mask can't be part of any rule.
+    if page >= 128 {
+        return true;
+    }
+    if *seen & rules[page] != 0 {
+        return false;
+    }
+    *seen |= 1 << page;
+    true
+}
+
+/// Like [`visit_small`], for the rules of [`Repr::Large`].
+fn visit_large(
+    ids: &HashMap<usize, usize>,
+    rows: &[BitSet],
+    seen: &mut BitSet,
+    page: usize,
+) -> bool {
+    let Some(&id) = ids.get(&page) else {
+        return true;
+    };
+    if seen.intersects(&rows[id]) {
+        return false;
+    }
+    seen.insert(id);
+    true
 }
 
-pub fn parse_rules(i: &[u8]) -> IResult<&[u8], [u128; 100]> {
+/// A `before|after` line. A number followed by `,` starts an update, which ends the rules
+/// section; any other line starting with a number has to be a whole rule, so a malformed rule
+/// fails where it goes wrong.
+fn parse_rule(i: &[u8]) -> IResult<&[u8], (usize, usize)> {
+    if let [b0, b1, b'|', a0, a1, b'\n', rest @ ..] = i {
+        if let (Some(before), Some(after)) = (two_digits(*b0, *b1), two_digits(*a0, *a1)) {
+            return Ok((rest, (before, after)));
+        }
+    }
+
+    terminated(
+        separated_pair(
+            parse_num,
+            preceded(not(tag(",")), cut(tag("|"))),
+            cut(parse_num),
+        ),
+        cut(line_ending),
+    )(i)
+}
+
+pub fn parse_rules(i: &[u8]) -> IResult<&[u8], RuleSet> {
     let _i = i;
-    let mut rules = [0; 100];
-    match terminated(
-        separated_pair(parse_num, take(1usize), parse_num),
-        line_ending,
-    )(_i)
-    {
-        Err(Err::Error(_)) => Err(Err::Error(Error::from_error_kind(i, ErrorKind::Many1))),
+    let mut rules = RuleSet::new();
+    match parse_rule(_i) {
+        Err(Err::Error(_)) => Err(Err::Error(error::Error::from_error_kind(
+            i,
+            error::ErrorKind::Many1,
+        ))),
         Err(e) => Err(e),
         Ok((i1, (before, after))) => {
-            rules[before] |= 1 << after;
+            rules.insert(before, after);
             let mut input = i1;
 
             loop {
                 let _input = input;
-                match terminated(
-                    separated_pair(parse_num, take(1usize), parse_num),
-                    line_ending,
-                )(_input)
-                {
+                match parse_rule(_input) {
                     Err(Err::Error(_)) => {
                         break;
                     }
                     Err(e) => return Err(e),
                     Ok((i, (before, after))) => {
-                        rules[before] |= 1 << after;
+                        rules.insert(before, after);
                         input = i;
                     }
                 }
@@ -51,90 +370,130 @@ pub fn parse_rules(i: &[u8]) -> IResult<&[u8], [u128; 100]> {
     }
 }
 
-fn parse_update(rules: &[u128; 100]) -> impl FnMut(&[u8]) -> IResult<&[u8], usize> + use<'_> {
-    move |mut i| {
-        let original_i = i;
-
-        let mut offset_to_middle = 0;
-
-        let (i1, initial_page) = parse_num(i)?;
-        i = i1;
+/// Parses an update, checking its pages in order with `visit`, which is handed the state of
+/// the update so far. Returns its middle page if every page passed, or 0.
+fn parse_update<S: Default>(
+    visit: impl Fn(&mut S, usize) -> bool,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], usize> {
+    let mut pages = Vec::new();
+    move |i| {
+        pages.clear();
+        let mut seen = S::default();
 
-        let mut state = 1 << initial_page;
+        let (mut i, page) = parse_num(i)?;
+        visit(&mut seen, page);
+        pages.push(page);
 
         loop {
             match preceded(tag(","), parse_num)(i) {
-                Err(Err::Error(_)) => {
-                    let (_, middle) = parse_num(&original_i[offset_to_middle..])?;
-
-                    return Ok((i, middle));
-                }
+                Err(Err::Error(_)) => break,
                 Err(e) => return Err(e),
-                Ok((i1, page)) => {
-                    i = i1;
-                    let must_be_after = rules[page];
-
-                    if state & must_be_after != 0 {
-                        return value(0, not_line_ending)(i);
+                Ok((rest, page)) => {
+                    i = rest;
+                    if !visit(&mut seen, page) {
+                        return skip_pages(i);
                     }
-
-                    state |= 1 << page;
+                    pages.push(page);
                 }
             }
+        }
 
-            let (i1, page) = preceded(take(1usize), parse_num)(i)?;
-            i = i1;
-            let must_be_after = rules[page];
+        Ok((i, pages[pages.len() / 2]))
+    }
+}
 
-            if state & must_be_after != 0 {
-                return value(0, not_line_ending)(i);
-            }
+/// Reads the remaining pages of an update that is out of order, which adds nothing to the sum.
+fn skip_pages(i: &[u8]) -> IResult<&[u8], usize> {
+    value(0, many0_count(preceded(tag(","), parse_num)))(i)
+}
 
-            state |= 1 << page;
+/// Sums what `parse_update` returns for each of the updates. Fails with nom's `TooLarge` at the
+/// update that takes the sum past `usize::MAX`.
+fn sum_updates(
+    i: &[u8],
+    mut parse_update: impl FnMut(&[u8]) -> IResult<&[u8], usize>,
+) -> IResult<&[u8], usize> {
+    let mut update = |i| terminated(&mut parse_update, opt(line_ending))(i);
 
-            offset_to_middle += 3;
+    let (mut i, mut total) = update(i)?;
+    loop {
+        match update(i) {
+            Err(Err::Error(_)) => return Ok((i, total)),
+            Err(e) => return Err(e),
+            Ok((rest, middle)) => {
+                total = total.checked_add(middle).ok_or_else(|| {
+                    Err::Failure(error::Error::new(i, error::ErrorKind::TooLarge))
+                })?;
+                i = rest;
+            }
         }
     }
 }
 
-fn parse_updates<'a, 'b>(i: &'a [u8], rules: &'b [u128; 100]) -> IResult<&'a [u8], usize> {
-    fold_many1(
-        terminated(parse_update(rules), opt(line_ending)),
-        || 0,
-        |mut acc, middle| {
-            acc += middle;
-            acc
-        },
-    )(i)
+fn parse_updates<'a>(i: &'a [u8], rules: &RuleSet) -> IResult<&'a [u8], usize> {
+    // Matching on the representation once, rather than for every page, keeps checking a page
+    // down to a few instructions.
+    match &rules.repr {
+        Repr::Small(rules) => {
+            sum_updates(i, parse_update(|seen, page| visit_small(rules, seen, page)))
+        }
+        Repr::Large { ids, rows, .. } => sum_updates(
+            i,
+            parse_update(|seen, page| visit_large(ids, rows, seen, page)),
+        ),
+    }
 }
 
-#[aoc(day5, part1)]
-pub fn part1(input: &str) -> usize {
-    let input = input.as_bytes();
-
-    let (input, rules) = parse_rules(input).unwrap();
+/// Parses the rules and the blank line separating them from the updates.
+fn parse_rules_section(input: &[u8]) -> Result<(&[u8], RuleSet), Error> {
+    let (rest, rules) = parse_rules(input).map_err(|err| match err {
+        Err::Failure(e) if e.code == error::ErrorKind::Tag => {
+            Error::at_rest(input, e.input, ErrorKind::ExpectedSeparator)
+        }
+        Err::Failure(e) if e.code == error::ErrorKind::CrLf => {
+            Error::at_rest(input, e.input, ErrorKind::ExpectedLineEnding)
+        }
+        err => Error::from_nom(input, err, ErrorKind::ExpectedNumber),
+    })?;
 
-    let (_, total) = parse_updates(&input[1..], &rules).unwrap();
+    let (rest, _) = line_ending(rest)
+        .map_err(|err| Error::from_nom(input, err, ErrorKind::ExpectedLineEnding))?;
 
-    total
+    Ok((rest, rules))
 }
 
-fn bubble_sort(list: &mut [usize], rules: &[u128; 100]) -> bool {
-    let mut n = list.len();
-    let mut swapped = false;
-    let half_n = list.len() / 2;
-    while n > half_n {
-        let mut new_n = 0;
-        for i in 1..n {
-            if rules[list[i]] & (1 << list[i - 1]) != 0 {
-                list.swap(i, i - 1);
-                new_n = i;
-                swapped = true;
-            }
+/// Parses the rules section, then hands the updates to `parse_updates`, checking that all of
+/// the input was consumed.
+fn solve<F>(input: &str, parse_updates: F) -> Result<usize, Error>
+where
+    F: for<'a> Fn(&'a [u8], &RuleSet) -> IResult<&'a [u8], usize>,
+{
+    let input = normalize_line_endings(input);
+    let input = input.as_bytes();
+
+    let (rest, rules) = parse_rules_section(input)?;
+
+    let (rest, total) = parse_updates(rest, &rules).map_err(|err| match err {
+        Err::Failure(e) if e.code == error::ErrorKind::Verify => {
+            Error::at_rest(input, e.input, ErrorKind::RuleCycle)
         }
-        n = new_n
+        err => Error::from_nom(input, err, ErrorKind::ExpectedNumber),
+    })?;
+
+    if !rest.is_empty() {
+        return Err(Error::at_rest(input, rest, ErrorKind::TrailingCharacters));
     }
-    swapped
+
+    Ok(total)
+}
+
+pub fn try_part1(input: &str) -> Result<usize, Error> {
+    solve(input, parse_updates)
+}
+
+#[aoc(day5, part1)]
+pub fn part1(input: &str) -> usize {
+    try_part1(input).unwrap_or_else(|err| panic!("{err}"))
 }
 
 pub fn comma_separated_nums(
@@ -169,50 +528,340 @@ pub fn comma_separated_nums(
     }
 }
 
-fn parse_incorrect_update<'a, 'b>(
-    rules: &'a [u128; 100],
-) -> impl FnMut(&[u8]) -> IResult<&[u8], usize> + use<'a, 'b> {
+pub fn try_part2(input: &str) -> Result<usize, Error> {
+    solve(input, parse_reordered_updates)
+}
+
+#[aoc(day5, part2)]
+pub fn part2(input: &str) -> usize {
+    try_part2(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// A cycle in the ordering rules, which leaves any update containing all of its pages with no
+/// valid order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError {
+    /// The pages of the cycle, starting from the smallest. Each page has a rule placing it before
+    /// the next one, and the last page has a rule placing it before the first.
+    pub cycle: Vec<usize>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ordering rules contain the cycle ")?;
+        for page in &self.cycle {
+            write!(f, "{page} -> ")?;
+        }
+        write!(f, "{}", self.cycle[0])
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// Finds a cycle among `pages`, every one of which must have a rule placing one of `pages`
+/// before it.
+fn find_cycle(rules: &RuleSet, pages: &[usize]) -> Vec<usize> {
+    // Walking backwards along the rules can never get stuck, so it must eventually revisit a
+    // page.
+    let mut path = vec![pages[0]];
+    loop {
+        let current = path[path.len() - 1];
+        let before = *pages
+            .iter()
+            .find(|&&before| rules.contains(before, current))
+            .expect("every remaining page has a predecessor");
+
+        if let Some(start) = path.iter().position(|&page| page == before) {
+            let mut cycle = path.split_off(start);
+            cycle.reverse();
+            let smallest = cycle.iter().position_min().unwrap_or(0);
+            cycle.rotate_left(smallest);
+            return cycle;
+        }
+
+        path.push(before);
+    }
+}
+
+/// Orders `update` with Kahn's algorithm on the rule graph restricted to its pages. Pages that
+/// are not constrained relative to each other keep their original relative order.
+fn topological_order(rules: &RuleSet, update: &[usize]) -> Result<Vec<usize>, CycleError> {
+    let words = update.len().div_ceil(64);
+    let matrix = rules.rules_between(update);
+    // The positions of the pages `update[i]` must come before.
+    let successors = |i: usize| {
+        let row = &matrix[i * words..(i + 1) * words];
+        (0..update.len()).filter(move |&j| row[j / 64] & (1 << (j % 64)) != 0)
+    };
+
+    let mut in_degree = vec![0; update.len()];
+    for i in 0..update.len() {
+        for j in successors(i) {
+            in_degree[j] += 1;
+        }
+    }
+
+    let mut placed = vec![false; update.len()];
+    let mut order = Vec::with_capacity(update.len());
+
+    while order.len() < update.len() {
+        let Some(i) = (0..update.len()).find(|&i| !placed[i] && in_degree[i] == 0) else {
+            let remaining = update
+                .iter()
+                .zip(&placed)
+                .filter(|(_, &placed)| !placed)
+                .map(|(&page, _)| page)
+                .collect::<Vec<_>>();
+
+            return Err(CycleError {
+                cycle: find_cycle(rules, &remaining),
+            });
+        };
+
+        placed[i] = true;
+        order.push(update[i]);
+        for j in successors(i) {
+            in_degree[j] -= 1;
+        }
+    }
+
+    Ok(order)
+}
+
+/// Sorts `update` so that it satisfies every rule between its pages. Pages that are not
+/// constrained relative to each other keep their original relative order.
+///
+/// Returns whether any page moved.
+pub fn reorder_update(rules: &RuleSet, update: &mut [usize]) -> Result<bool, CycleError> {
+    if let Some(moved) = rules.reorder_total(update) {
+        return Ok(moved);
+    }
+
+    let order = topological_order(rules, update)?;
+
+    let moved = order != update;
+    update.copy_from_slice(&order);
+    Ok(moved)
+}
+
+fn parse_reordered_update(rules: &RuleSet) -> impl FnMut(&[u8]) -> IResult<&[u8], usize> + '_ {
     let mut list = Vec::new();
     move |i| {
-        let (i, ()) = comma_separated_nums(&mut list)(i)?;
-
-        let swapped = bubble_sort(&mut list, rules);
+        let (rest, ()) = comma_separated_nums(&mut list)(i)?;
 
-        if swapped {
-            Ok((i, list[list.len() / 2]))
-        } else {
-            Ok((i, 0))
+        match reorder_update(rules, &mut list) {
+            Ok(true) => Ok((rest, list[list.len() / 2])),
+            Ok(false) => Ok((rest, 0)),
+            Err(_) => Err(Err::Failure(error::Error::new(i, error::ErrorKind::Verify))),
         }
     }
 }
 
-fn parse_incorrect_updates<'a, 'b>(
-    i: &'a [u8],
-    rules: &'b [u128; 100],
-) -> IResult<&'a [u8], usize> {
-    fold_many1(
-        terminated(parse_incorrect_update(rules), opt(line_ending)),
-        || 0,
-        |mut acc, middle| {
-            acc += middle;
-            acc
-        },
-    )(i)
+fn parse_reordered_updates<'a>(i: &'a [u8], rules: &RuleSet) -> IResult<&'a [u8], usize> {
+    sum_updates(i, parse_reordered_update(rules))
 }
 
-#[aoc(day5, part2)]
-pub fn part2(input: &str) -> usize {
+/// The `Topo` benchmark variant. Part 2 reorders updates with [`reorder_update`] too, so this is
+/// [`try_part2`] under another name.
+pub fn try_part2_topo(input: &str) -> Result<usize, Error> {
+    try_part2(input)
+}
+
+#[aoc(day5, part2, Topo)]
+pub fn part2_topo(input: &str) -> usize {
+    try_part2_topo(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// An update read by [`parse_input`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Update {
+    /// 1-based line of the update in the input.
+    pub line: usize,
+    pub pages: Vec<usize>,
+}
+
+/// Parses the whole input into its rules and updates.
+pub fn parse_input(input: &str) -> Result<(RuleSet, Vec<Update>), Error> {
+    let input = normalize_line_endings(input);
     let input = input.as_bytes();
-    let (input, rules) = parse_rules(input).unwrap();
 
-    let (_, total) = parse_incorrect_updates(&input[1..], &rules).unwrap();
+    let (mut rest, rules) = parse_rules_section(input)?;
+
+    let mut line = memchr::memchr_iter(b'\n', &input[..input.len() - rest.len()]).count() + 1;
+    let mut updates = Vec::new();
+
+    while !rest.is_empty() {
+        let mut pages = Vec::new();
+
+        let (r, ()) = comma_separated_nums(&mut pages)(rest)
+            .map_err(|err| Error::from_nom(input, err, ErrorKind::ExpectedNumber))?;
+        let (r, _) = alt((line_ending, eof))(r)
+            .map_err(|err| Error::from_nom(input, err, ErrorKind::ExpectedLineEnding))?;
+
+        updates.push(Update { line, pages });
+        line += 1;
+        rest = r;
+    }
+
+    Ok((rules, updates))
+}
+
+/// A rule broken by an update: the rule `earlier_page|later_page` requires `earlier_page` to be
+/// printed first, but the update has it after `later_page`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub earlier_page: usize,
+    pub later_page: usize,
+    /// The 0-based positions of `earlier_page` and `later_page` in the update.
+    pub positions: (usize, usize),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}|{} broken: {} is at position {} but {} is at position {}",
+            self.earlier_page,
+            self.later_page,
+            self.earlier_page,
+            self.positions.0,
+            self.later_page,
+            self.positions.1
+        )
+    }
+}
+
+/// Lists every rule broken by `update`, in the order they are encountered reading the update
+/// from left to right. An update is correctly ordered exactly when this is empty.
+pub fn explain_update(rules: &RuleSet, update: &[usize]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    for (i, &page) in update.iter().enumerate() {
+        for (j, &earlier) in update[..i].iter().enumerate() {
+            if rules.contains(page, earlier) {
+                violations.push(Violation {
+                    earlier_page: page,
+                    later_page: earlier,
+                    positions: (i, j),
+                });
+            }
+        }
+    }
+
+    violations
+}
+
+/// An update of the input together with the rules it breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub update: Update,
+    pub violations: Vec<Violation>,
+}
+
+impl Explanation {
+    pub fn is_correct(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {}: ",
+            self.update.line,
+            self.update.pages.iter().join(",")
+        )?;
+
+        if self.is_correct() {
+            return write!(
+                f,
+                "correct, middle page {}",
+                self.update.pages[self.update.pages.len() / 2]
+            );
+        }
+
+        write!(f, "incorrect")?;
+        for violation in &self.violations {
+            write!(f, "\n    {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Explains every update of the input.
+pub fn explain(input: &str) -> Result<Vec<Explanation>, Error> {
+    let (rules, updates) = parse_input(input)?;
+
+    Ok(updates
+        .into_iter()
+        .map(|update| Explanation {
+            violations: explain_update(&rules, &update.pages),
+            update,
+        })
+        .collect())
+}
+
+/// Renders every rule as an edge `before -> after` of a Graphviz digraph.
+pub fn rules_to_dot(rules: &RuleSet) -> String {
+    let mut dot = String::from("digraph rules {\n");
+    for (before, after) in rules.iter() {
+        dot += &format!("    {before} -> {after};\n");
+    }
+    dot += "}\n";
+    dot
+}
+
+/// Renders the pages of `update`, in order, and the rules between them as a Graphviz digraph.
+/// With `highlight_violations`, the rules broken by the update are drawn in red.
+pub fn update_to_dot(rules: &RuleSet, update: &[usize], highlight_violations: bool) -> String {
+    let violations = if highlight_violations {
+        explain_update(rules, update)
+    } else {
+        Vec::new()
+    };
+
+    let mut dot = String::from("digraph update {\n");
+
+    for (position, page) in update.iter().enumerate() {
+        dot += &format!("    {page} [label=\"{page} (#{position})\"];\n");
+    }
+
+    let pages = update.iter().copied().collect::<HashSet<_>>();
+    for (before, after) in rules
+        .iter()
+        .filter(|(before, after)| pages.contains(before) && pages.contains(after))
+    {
+        let broken = violations
+            .iter()
+            .any(|violation| violation.earlier_page == before && violation.later_page == after);
+        if broken {
+            dot += &format!("    {before} -> {after} [color=red];\n");
+        } else {
+            dot += &format!("    {before} -> {after};\n");
+        }
+    }
 
-    total
+    dot += "}\n";
+    dot
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{input::line_ending_variants, test_util::XorShift};
+
+    const EXAMPLE: &str = "47|53\n97|13\n97|61\n97|47\n75|29\n61|13\n75|53\n29|13\n97|29\n53|29\n61|53\n97|53\n61|29\n47|13\n75|47\n97|75\n47|61\n75|61\n47|29\n75|13\n53|13\n\n75,47,61,53,29\n97,61,53,29,13\n75,29,13\n75,97,47,61,53\n61,13,29\n97,13,75,29,47";
+
+    /// The example with `offset` added to every page number.
+    fn shifted_example(offset: usize) -> String {
+        regex::Regex::new(r"\d+")
+            .unwrap()
+            .replace_all(EXAMPLE, |page: &regex::Captures| {
+                (page[0].parse::<usize>().unwrap() + offset).to_string()
+            })
+            .into_owned()
+    }
 
     #[test]
     fn part1_example() {
@@ -223,4 +872,339 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2("47|53\n97|13\n97|61\n97|47\n75|29\n61|13\n75|53\n29|13\n97|29\n53|29\n61|53\n97|53\n61|29\n47|13\n75|47\n97|75\n47|61\n75|61\n47|29\n75|13\n53|13\n\n75,47,61,53,29\n97,61,53,29,13\n75,29,13\n75,97,47,61,53\n61,13,29\n97,13,75,29,47"), 123);
     }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(
+            try_part1("x|53\n\n75,47\n"),
+            Err(Error::new(1, 1, ErrorKind::ExpectedNumber))
+        );
+        assert_eq!(
+            try_part1("47|53\n75,47,61\n"),
+            Err(Error::new(2, 1, ErrorKind::ExpectedLineEnding))
+        );
+        assert_eq!(
+            try_part2("47|53\n\n75,47,53\nabc"),
+            Err(Error::new(4, 1, ErrorKind::TrailingCharacters))
+        );
+    }
+
+    #[test]
+    fn update_separators() {
+        assert_eq!(try_part1("1|2\n\n3,4\n5\n"), Ok(9));
+        assert_eq!(try_part1("1|2\n\n3,4\n5,6,7\n"), Ok(10));
+
+        for (input, column) in [("1|2\n\n3,4x5\n", 4), ("1|2\n\n3;4\n", 2)] {
+            let err = Err(Error::new(3, column, ErrorKind::TrailingCharacters));
+            assert_eq!(try_part1(input), err, "{input:?}");
+            assert_eq!(try_part2(input), err, "{input:?}");
+        }
+
+        // The rest of an out of order update is still checked.
+        let err = Err(Error::new(3, 6, ErrorKind::TrailingCharacters));
+        assert_eq!(try_part1("47|53\n\n53,47,x"), err);
+        assert_eq!(try_part2("47|53\n\n53,47,x"), err);
+    }
+
+    #[test]
+    fn rule_separators() {
+        for (input, line, column, kind) in [
+            ("1x2\n\n1,2\n", 1, 2, ErrorKind::ExpectedSeparator),
+            ("1|2\n3;4\n\n3,4\n", 2, 2, ErrorKind::ExpectedSeparator),
+            ("1|x\n\n1,2\n", 1, 3, ErrorKind::ExpectedNumber),
+            ("1|2x\n\n1,2\n", 1, 4, ErrorKind::ExpectedLineEnding),
+        ] {
+            let err = Err(Error::new(line, column, kind));
+            assert_eq!(try_part1(input), err, "{input:?}");
+            assert_eq!(try_part2(input), err, "{input:?}");
+            assert_eq!(try_part2_topo(input), err, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn number_overflow() {
+        let err = Err(Error::new(1, 1, ErrorKind::NumberOverflow));
+        let input = "99999999999999999999999|1\n\n1,2\n";
+        assert_eq!(try_part1(input), err);
+        assert_eq!(try_part2(input), err);
+        assert_eq!(try_part2_topo(input), err);
+
+        let err = Err(Error::new(3, 3, ErrorKind::NumberOverflow));
+        let input = "1|2\n\n1,99999999999999999999999\n";
+        assert_eq!(try_part1(input), err);
+        assert_eq!(try_part2(input), err);
+        assert_eq!(try_part2_topo(input), err);
+
+        // The middle pages fit, but their sum doesn't.
+        let err = Err(Error::new(4, 1, ErrorKind::NumberOverflow));
+        let input = "1|2\n\n18446744073709551615\n18446744073709551615\n";
+        assert_eq!(try_part1(input), err);
+        let input =
+            "18446744073709551615|1\n\n5,1,18446744073709551615\n5,1,18446744073709551615\n";
+        assert_eq!(try_part2(input), err);
+        assert_eq!(try_part2_topo(input), err);
+    }
+
+    #[test]
+    fn line_endings() {
+        for input in line_ending_variants("47|53\n97|13\n97|61\n97|47\n75|29\n61|13\n75|53\n29|13\n97|29\n53|29\n61|53\n97|53\n61|29\n47|13\n75|47\n97|75\n47|61\n75|61\n47|29\n75|13\n53|13\n\n75,47,61,53,29\n97,61,53,29,13\n75,29,13\n75,97,47,61,53\n61,13,29\n97,13,75,29,47\n") {
+            assert_eq!(try_part1(&input), Ok(143));
+            assert_eq!(try_part2(&input), Ok(123));
+        }
+    }
+
+    #[test]
+    fn large_page_numbers() {
+        // Three updates are correctly ordered and three are not, so each middle page sum grows by
+        // three times the offset.
+        for offset in [100, 1000, 123456] {
+            let input = shifted_example(offset);
+            assert_eq!(try_part1(&input), Ok(143 + 3 * offset));
+            assert_eq!(try_part2(&input), Ok(123 + 3 * offset));
+        }
+    }
+
+    #[test]
+    fn rule_set_grows_past_bitmask() {
+        let mut rules = RuleSet::new();
+        rules.insert(97, 13);
+        rules.insert(5, 127);
+        assert!(matches!(rules.repr, Repr::Small(_)));
+
+        rules.insert(128, 5);
+        rules.insert(1000, 97);
+        assert!(matches!(rules.repr, Repr::Large { .. }));
+
+        assert!(rules.contains(97, 13));
+        assert!(rules.contains(1000, 97));
+        assert!(!rules.contains(13, 97));
+        assert!(!rules.contains(2000, 97));
+        assert_eq!(
+            rules.iter().collect::<Vec<_>>(),
+            [(5, 127), (97, 13), (128, 5), (1000, 97)]
+        );
+    }
+
+    #[test]
+    fn part2_topo_example() {
+        assert_eq!(part2_topo(EXAMPLE), 123);
+    }
+
+    #[test]
+    fn reorder_example_updates() {
+        let (_, rules) = parse_rules(EXAMPLE.as_bytes()).unwrap();
+
+        let mut update = [75, 47, 61, 53, 29];
+        assert_eq!(reorder_update(&rules, &mut update), Ok(false));
+        assert_eq!(update, [75, 47, 61, 53, 29]);
+
+        for (mut update, expected) in [
+            ([75, 97, 47, 61, 53], [97, 75, 47, 61, 53]),
+            ([97, 13, 75, 29, 47], [97, 75, 47, 29, 13]),
+        ] {
+            assert_eq!(reorder_update(&rules, &mut update), Ok(true));
+            assert_eq!(update, expected);
+        }
+    }
+
+    #[test]
+    fn reorder_matches_naive_order() {
+        // Repeatedly takes the first page that no remaining page must come before.
+        let naive = |rules: &RuleSet, update: &[usize]| {
+            let mut remaining = update.to_vec();
+            let mut order = Vec::new();
+            while !remaining.is_empty() {
+                let next = remaining.iter().position(|&page| {
+                    !remaining.iter().any(|&before| rules.contains(before, page))
+                })?;
+                order.push(remaining.remove(next));
+            }
+            Some(order)
+        };
+
+        let mut rng = XorShift::new(5);
+        for _ in 0..2000 {
+            let pages = 1 + rng.below(12);
+            let mut rules = RuleSet::new();
+            if rng.below(2) == 0 {
+                // A rule between every pair of pages, as in the puzzle input.
+                let mut order = (0..pages as usize).collect::<Vec<_>>();
+                for i in 1..order.len() {
+                    order.swap(i, rng.below(i as u64 + 1) as usize);
+                }
+                for (i, &before) in order.iter().enumerate() {
+                    for &after in &order[i + 1..] {
+                        rules.insert(before, after);
+                    }
+                }
+            } else {
+                for _ in 0..rng.below(pages * pages / 2 + 1) {
+                    rules.insert(rng.below(pages) as usize, rng.below(pages) as usize);
+                }
+            }
+            let mut large = rules.clone();
+            large.insert(1000, 1001);
+
+            let update = (0..1 + rng.below(10))
+                .map(|_| rng.below(pages) as usize)
+                .collect::<Vec<_>>();
+            let expected = naive(&rules, &update);
+
+            for rules in [&rules, &large] {
+                let mut reordered = update.clone();
+                match reorder_update(rules, &mut reordered) {
+                    Ok(moved) => {
+                        assert_eq!(Some(&reordered), expected.as_ref(), "{update:?}");
+                        assert_eq!(moved, reordered != update);
+                    }
+                    Err(_) => assert_eq!(expected, None, "{update:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reorder_reports_cycle() {
+        let (_, rules) = parse_rules(b"3|1\n1|2\n2|3\n4|1\n").unwrap();
+
+        assert_eq!(
+            reorder_update(&rules, &mut [4, 3, 2, 1]),
+            Err(CycleError {
+                cycle: vec![1, 2, 3]
+            })
+        );
+        assert_eq!(
+            try_part2_topo("1|2\n2|1\n\n1,3\n1,2\n"),
+            Err(Error::new(5, 1, ErrorKind::RuleCycle))
+        );
+    }
+
+    #[test]
+    fn example_rules_are_consistent() {
+        let (_, rules) = parse_rules(EXAMPLE.as_bytes()).unwrap();
+
+        assert_eq!(rules.contradictions(), []);
+        assert_eq!(rules.check_update(&[97, 13, 75, 29, 47]), Ok(()));
+    }
+
+    #[test]
+    fn detects_contradictions() {
+        let (_, rules) = parse_rules(b"47|53\n5|5\n53|47\n1|2\n200|200\n").unwrap();
+
+        assert_eq!(
+            rules.contradictions(),
+            [
+                CycleError { cycle: vec![5] },
+                CycleError {
+                    cycle: vec![47, 53]
+                },
+                CycleError { cycle: vec![200] },
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_update_cycles() {
+        let (_, rules) = parse_rules(b"1|2\n2|3\n3|4\n4|2\n5|5\n").unwrap();
+
+        assert_eq!(rules.check_update(&[1, 2, 3]), Ok(()));
+        assert_eq!(
+            rules.check_update(&[4, 1, 3, 2]),
+            Err(CycleError {
+                cycle: vec![2, 3, 4]
+            })
+        );
+        assert_eq!(
+            rules.check_update(&[1, 5]),
+            Err(CycleError { cycle: vec![5] })
+        );
+    }
+
+    #[test]
+    fn explain_example_updates() {
+        let explanations = explain(EXAMPLE).unwrap();
+
+        assert_eq!(
+            explanations
+                .iter()
+                .filter(|explanation| explanation.is_correct())
+                .map(|explanation| explanation.update.line)
+                .collect::<Vec<_>>(),
+            [23, 24, 25]
+        );
+        assert_eq!(
+            explanations[5].violations,
+            [
+                Violation {
+                    earlier_page: 75,
+                    later_page: 13,
+                    positions: (2, 1)
+                },
+                Violation {
+                    earlier_page: 29,
+                    later_page: 13,
+                    positions: (3, 1)
+                },
+                Violation {
+                    earlier_page: 47,
+                    later_page: 13,
+                    positions: (4, 1)
+                },
+                Violation {
+                    earlier_page: 47,
+                    later_page: 29,
+                    positions: (4, 3)
+                },
+            ]
+        );
+        assert_eq!(
+            explanations[0].to_string(),
+            "line 23: 75,47,61,53,29: correct, middle page 61"
+        );
+        assert_eq!(
+            explanations[4].to_string(),
+            "line 27: 61,13,29: incorrect\n    29|13 broken: 29 is at position 2 but 13 is at position 1"
+        );
+    }
+
+    #[test]
+    fn explanations_match_part1() {
+        let total = explain(EXAMPLE)
+            .unwrap()
+            .iter()
+            .filter(|explanation| explanation.is_correct())
+            .map(|explanation| explanation.update.pages[explanation.update.pages.len() / 2])
+            .sum::<usize>();
+
+        assert_eq!(total, part1(EXAMPLE));
+    }
+
+    #[test]
+    fn rules_dot() {
+        let (_, rules) = parse_rules(b"97|13\n47|53\n97|47\n").unwrap();
+
+        assert_eq!(
+            rules_to_dot(&rules),
+            "digraph rules {\n    47 -> 53;\n    97 -> 13;\n    97 -> 47;\n}\n"
+        );
+    }
+
+    #[test]
+    fn update_dot() {
+        let (_, rules) = parse_rules(b"97|13\n47|53\n97|47\n61|13\n").unwrap();
+
+        let expected = "digraph update {
+    47 [label=\"47 (#0)\"];
+    97 [label=\"97 (#1)\"];
+    13 [label=\"13 (#2)\"];
+    97 -> 13;
+    97 -> 47 [color=red];
+}
+";
+        assert_eq!(update_to_dot(&rules, &[47, 97, 13], true), expected);
+        assert_eq!(
+            update_to_dot(&rules, &[47, 97, 13], false),
+            expected.replace(" [color=red]", "")
+        );
+    }
 }
@@ -0,0 +1,110 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    ExpectedNumber,
+    NumberOverflow,
+    ExpectedSeparator,
+    ExpectedLineEnding,
+    TrailingCharacters,
+    RuleCycle,
+    UnevenRows,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::ExpectedNumber => "expected a number",
+            ErrorKind::NumberOverflow => "number is too large",
+            ErrorKind::ExpectedSeparator => "expected a separator",
+            ErrorKind::ExpectedLineEnding => "expected a line ending",
+            ErrorKind::TrailingCharacters => "unexpected trailing characters",
+            ErrorKind::RuleCycle => "ordering rules contain a cycle",
+            ErrorKind::UnevenRows => "row is not as wide as the first row",
+        })
+    }
+}
+
+/// An error in a puzzle input. `line` and `column` are 1-based, and `column` counts bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+impl Error {
+    pub fn new(line: usize, column: usize, kind: ErrorKind) -> Self {
+        Self { line, column, kind }
+    }
+
+    /// Builds an error for the byte at `offset` in `input`.
+    pub fn at(input: &[u8], offset: usize, kind: ErrorKind) -> Self {
+        let before = &input[..offset.min(input.len())];
+        let line_start = memchr::memrchr(b'\n', before).map_or(0, |i| i + 1);
+
+        Self {
+            line: memchr::memchr_iter(b'\n', before).count() + 1,
+            column: before.len() - line_start + 1,
+            kind,
+        }
+    }
+
+    /// Builds an error for the position at which `rest`, a suffix of `input`, starts.
+    pub fn at_rest(input: &[u8], rest: &[u8], kind: ErrorKind) -> Self {
+        Self::at(input, input.len() - rest.len(), kind)
+    }
+
+    /// Converts a failed `nom` parse of `input` into an error of the given kind. Parsers report
+    /// numbers that don't fit their type with nom's `TooLarge`, which becomes
+    /// [`ErrorKind::NumberOverflow`] whatever `kind` is.
+    pub(crate) fn from_nom(
+        input: &[u8],
+        err: nom::Err<nom::error::Error<&[u8]>>,
+        kind: ErrorKind,
+    ) -> Self {
+        match err {
+            nom::Err::Error(e) | nom::Err::Failure(e)
+                if e.code == nom::error::ErrorKind::TooLarge =>
+            {
+                Self::at_rest(input, e.input, ErrorKind::NumberOverflow)
+            }
+            nom::Err::Error(e) | nom::Err::Failure(e) => Self::at_rest(input, e.input, kind),
+            nom::Err::Incomplete(_) => Self::at(input, input.len(), kind),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_from_offset() {
+        let input = b"12 34\n56 x\n";
+        assert_eq!(
+            Error::at(input, 0, ErrorKind::ExpectedNumber),
+            Error::new(1, 1, ErrorKind::ExpectedNumber)
+        );
+        assert_eq!(
+            Error::at(input, 9, ErrorKind::ExpectedNumber),
+            Error::new(2, 4, ErrorKind::ExpectedNumber)
+        );
+        assert_eq!(
+            Error::at(input, input.len(), ErrorKind::ExpectedNumber),
+            Error::new(3, 1, ErrorKind::ExpectedNumber)
+        );
+    }
+}
@@ -0,0 +1,674 @@
+use std::borrow::Cow;
+
+use crate::{Error, ErrorKind};
+
+/// A cell of a [`Grid`], counted from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Point {
+    pub const fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+
+    /// The point `steps` steps away in `direction`, if it has no negative coordinate.
+    pub fn step(self, direction: Direction, steps: isize) -> Option<Self> {
+        Some(Self {
+            row: self
+                .row
+                .checked_add_signed(direction.row.checked_mul(steps)?)?,
+            col: self
+                .col
+                .checked_add_signed(direction.col.checked_mul(steps)?)?,
+        })
+    }
+
+    /// The adjacent point in `direction`, if it has no negative coordinate.
+    pub fn offset(self, direction: Direction) -> Option<Self> {
+        self.step(direction, 1)
+    }
+}
+
+/// A signed offset between neighboring cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Direction {
+    pub row: isize,
+    pub col: isize,
+}
+
+impl Direction {
+    pub const N: Self = Self::new(-1, 0);
+    pub const NE: Self = Self::new(-1, 1);
+    pub const E: Self = Self::new(0, 1);
+    pub const SE: Self = Self::new(1, 1);
+    pub const S: Self = Self::new(1, 0);
+    pub const SW: Self = Self::new(1, -1);
+    pub const W: Self = Self::new(0, -1);
+    pub const NW: Self = Self::new(-1, -1);
+
+    /// All eight directions, clockwise from north.
+    pub const ALL: [Self; 8] = [
+        Self::N,
+        Self::NE,
+        Self::E,
+        Self::SE,
+        Self::S,
+        Self::SW,
+        Self::W,
+        Self::NW,
+    ];
+
+    pub const ORTHOGONAL: [Self; 4] = [Self::N, Self::E, Self::S, Self::W];
+
+    pub const DIAGONAL: [Self; 4] = [Self::NE, Self::SE, Self::SW, Self::NW];
+
+    pub const fn new(row: isize, col: isize) -> Self {
+        Self { row, col }
+    }
+
+    pub const fn reverse(self) -> Self {
+        Self::new(-self.row, -self.col)
+    }
+}
+
+/// A rectangle of cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub top_left: Point,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub const fn new(top_left: Point, width: usize, height: usize) -> Self {
+        Self {
+            top_left,
+            width,
+            height,
+        }
+    }
+
+    /// One past the last row. Regions reaching past `usize::MAX` are cut off there.
+    fn bottom(&self) -> usize {
+        self.top_left.row.saturating_add(self.height)
+    }
+
+    /// One past the last column, cut off at `usize::MAX` like [`Region::bottom`].
+    fn right(&self) -> usize {
+        self.top_left.col.saturating_add(self.width)
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        (self.top_left.row..self.bottom()).contains(&point.row)
+            && (self.top_left.col..self.right()).contains(&point.col)
+    }
+
+    /// The part of the region that is also in `other`.
+    pub fn intersect(&self, other: Region) -> Region {
+        let row = self.top_left.row.max(other.top_left.row);
+        let col = self.top_left.col.max(other.top_left.col);
+        let bottom = self.bottom().min(other.bottom());
+        let right = self.right().min(other.right());
+
+        Region::new(
+            Point::new(row, col),
+            right.saturating_sub(col),
+            bottom.saturating_sub(row),
+        )
+    }
+
+    /// Every point in the region, row by row.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let (top_left, bottom, right) = (self.top_left, self.bottom(), self.right());
+        (top_left.row..bottom)
+            .flat_map(move |row| (top_left.col..right).map(move |col| Point::new(row, col)))
+    }
+}
+
+/// What [`check_rows`] does with rows that aren't as wide as the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ragged {
+    /// Fail at the first row that isn't as wide as the first one.
+    #[default]
+    Reject,
+    /// Fill the missing cells of shorter rows with [`EMPTY`], out to the widest row.
+    Pad,
+}
+
+/// The cell [`Ragged::Pad`] fills missing cells with.
+pub const EMPTY: u8 = b' ';
+
+/// Makes newline-separated rows fit a [`Grid`], which needs them all to be equally wide.
+///
+/// The final newline is optional. Rectangular rows are borrowed as-is.
+pub fn check_rows(bytes: &[u8], ragged: Ragged) -> Result<Cow<'_, [u8]>, Error> {
+    let bytes_without_newline = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    if bytes_without_newline.is_empty() {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let len = bytes_without_newline.len();
+    let first = memchr::memchr(b'\n', bytes_without_newline).unwrap_or(len);
+
+    // The rows are even when there is a newline every `first + 1` bytes and nowhere else, which
+    // is much faster to check than splitting the rows.
+    let even = (len + 1).is_multiple_of(first + 1)
+        && memchr::memchr_iter(b'\n', bytes_without_newline).count() == (len + 1) / (first + 1) - 1
+        && bytes_without_newline[first..]
+            .iter()
+            .step_by(first + 1)
+            .all(|&c| c == b'\n');
+    if even {
+        return Ok(Cow::Borrowed(bytes));
+    }
+
+    let rows = bytes_without_newline.split(|&c| c == b'\n');
+    let uneven = rows.clone().position(|row| row.len() != first);
+    let Some(uneven) = uneven else {
+        return Ok(Cow::Borrowed(bytes));
+    };
+
+    match ragged {
+        Ragged::Reject => {
+            let len = rows.clone().nth(uneven).map_or(0, <[u8]>::len);
+            Err(Error::new(
+                uneven + 1,
+                len.min(first) + 1,
+                ErrorKind::UnevenRows,
+            ))
+        }
+        Ragged::Pad => {
+            let width = rows.clone().map(<[u8]>::len).max().unwrap_or(0);
+            let mut padded = Vec::with_capacity((width + 1) * rows.clone().count());
+            for row in rows {
+                padded.extend_from_slice(row);
+                padded.resize(padded.len() + width - row.len(), EMPTY);
+                padded.push(b'\n');
+            }
+            Ok(Cow::Owned(padded))
+        }
+    }
+}
+
+/// A rectangular grid of bytes, borrowed from newline-separated rows.
+///
+/// Every row is assumed to be as wide as the first one, which [`check_rows`] makes sure of.
+/// The final newline is optional.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid<'a> {
+    bytes: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Grid<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        // A grid without any newline is a single row.
+        let width = memchr::memchr(b'\n', bytes).unwrap_or(bytes.len());
+        let height = bytes.len().div_ceil(width + 1);
+
+        Self {
+            bytes,
+            width,
+            height,
+        }
+    }
+
+    /// The number of columns, not counting the newline.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        point.row < self.height && point.col < self.width
+    }
+
+    /// The region covering the whole grid.
+    pub fn bounds(&self) -> Region {
+        Region::new(Point::new(0, 0), self.width, self.height)
+    }
+
+    pub fn get(&self, point: Point) -> Option<u8> {
+        if !self.contains(point) {
+            return None;
+        }
+        self.bytes
+            .get(point.row * (self.width + 1) + point.col)
+            .copied()
+    }
+
+    /// The bytes of `row`, without its newline. Rows outside the grid are empty.
+    pub fn row_bytes(&self, row: usize) -> &'a [u8] {
+        if row >= self.height {
+            return &[];
+        }
+        let start = (row * (self.width + 1)).min(self.bytes.len());
+        let end = (start + self.width).min(self.bytes.len());
+        &self.bytes[start..end]
+    }
+
+    /// The byte `steps` steps away from `point` in `direction`, if that is in the grid.
+    pub fn get_step(&self, point: Point, direction: Direction, steps: isize) -> Option<u8> {
+        self.get(point.step(direction, steps)?)
+    }
+
+    /// Whether the cells from `start` onwards in `direction` spell out `word`.
+    pub fn reads(&self, start: Point, direction: Direction, word: &[u8]) -> bool {
+        let Some(last) = word.len().checked_sub(1) else {
+            return true;
+        };
+        let Some(end) = start.step(direction, last as isize) else {
+            return false;
+        };
+        if !self.contains(start) || !self.contains(end) {
+            return false;
+        }
+
+        // Both ends are in the grid, so every cell between them is too. Reading from the far
+        // end rules out most mismatches without rereading the cell at `start`.
+        let stride = direction.row * (self.width as isize + 1) + direction.col;
+        let first = start.row * (self.width + 1) + start.col;
+        word.iter().enumerate().rev().all(|(i, &letter)| {
+            self.bytes
+                .get(first.wrapping_add_signed(stride * i as isize))
+                == Some(&letter)
+        })
+    }
+
+    /// Calls `found` with every start and direction from which `word` reads, in row-major order
+    /// of the starts, like checking [`Grid::reads`] everywhere but faster.
+    pub fn find_word(
+        &self,
+        word: &[u8],
+        directions: &[Direction],
+        mut found: impl FnMut(Point, Direction),
+    ) {
+        let Some((&first, rest)) = word.split_first() else {
+            return;
+        };
+        let last = rest.len() as isize;
+        let row_len = self.width + 1;
+
+        for row in 0..self.height {
+            for col in memchr::memchr_iter(first, self.row_bytes(row)) {
+                let start = row * row_len + col;
+                for &direction in directions {
+                    let end = |from: usize, delta: isize| {
+                        from.checked_add_signed(delta.checked_mul(last)?)
+                    };
+                    let (Some(end_row), Some(end_col)) =
+                        (end(row, direction.row), end(col, direction.col))
+                    else {
+                        continue;
+                    };
+                    if end_row >= self.height || end_col >= self.width {
+                        continue;
+                    }
+
+                    // Both ends are in the grid, so every cell between them is too. Reading
+                    // from the far end rules out most mismatches soonest.
+                    let stride = direction.row * row_len as isize + direction.col;
+                    let reads = rest.iter().enumerate().rev().all(|(i, &letter)| {
+                        self.bytes
+                            .get(start.wrapping_add_signed(stride * (i as isize + 1)))
+                            == Some(&letter)
+                    });
+                    if reads {
+                        found(Point::new(row, col), direction);
+                    }
+                }
+            }
+        }
+    }
+
+    /// How many times `word` reads in one of `directions`, which is how often
+    /// [`Grid::find_word`] calls back. Whole runs of starts are compared at once without
+    /// branching on the letters, which is much faster when only the count is needed.
+    pub fn count_word(&self, word: &[u8], directions: &[Direction]) -> usize {
+        const CHUNK: usize = 64;
+
+        let Some(last) = word.len().checked_sub(1) else {
+            return 0;
+        };
+        let row_len = self.width + 1;
+
+        // The starts from which a word in `delta`'s direction stays within `size` cells.
+        let reach = |size: usize, delta: isize| {
+            let span = delta.unsigned_abs().checked_mul(last)?;
+            let room = size.checked_sub(span)?;
+            Some(if delta < 0 { span..size } else { 0..room })
+        };
+
+        let mut count = 0;
+        for &direction in directions {
+            let (Some(rows), Some(cols)) = (
+                reach(self.height, direction.row),
+                reach(self.width, direction.col),
+            ) else {
+                continue;
+            };
+            let stride = direction.row * row_len as isize + direction.col;
+
+            for row in rows {
+                for chunk_start in cols.clone().step_by(CHUNK) {
+                    let len = CHUNK.min(cols.end - chunk_start);
+                    let start = row * row_len + chunk_start;
+
+                    // The letters of the words starting in this chunk, one letter at a time.
+                    let mut hits = [true; CHUNK];
+                    for (i, &letter) in word.iter().enumerate() {
+                        let from = start.wrapping_add_signed(stride * i as isize);
+                        for (hit, &cell) in hits.iter_mut().zip(&self.bytes[from..from + len]) {
+                            *hit &= cell == letter;
+                        }
+                    }
+                    count += hits[..len].iter().filter(|&&hit| hit).count();
+                }
+            }
+        }
+        count
+    }
+
+    /// Every point in the grid, row by row.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        self.bounds().points()
+    }
+
+    /// The cells from `start` onwards in `direction`, up to the edge of the grid.
+    pub fn line(&self, start: Point, direction: Direction) -> Line<'a> {
+        Line {
+            grid: *self,
+            next: Some(start),
+            direction,
+        }
+    }
+
+    pub fn row(&self, row: usize) -> Line<'a> {
+        self.line(Point::new(row, 0), Direction::E)
+    }
+
+    pub fn column(&self, col: usize) -> Line<'a> {
+        self.line(Point::new(0, col), Direction::S)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Line<'a>> {
+        let grid = *self;
+        (0..self.height).map(move |row| grid.row(row))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = Line<'a>> {
+        let grid = *self;
+        (0..self.width).map(move |col| grid.column(col))
+    }
+
+    /// Every diagonal running down and to the right, starting from the bottom left corner.
+    pub fn diagonals(&self) -> impl Iterator<Item = Line<'a>> {
+        let grid = *self;
+        let starts = (1..self.height)
+            .rev()
+            .map(|row| Point::new(row, 0))
+            .chain((0..self.width).map(|col| Point::new(0, col)));
+        starts.map(move |start| grid.line(start, Direction::SE))
+    }
+
+    /// Every diagonal running down and to the left, starting from the top left corner.
+    pub fn anti_diagonals(&self) -> impl Iterator<Item = Line<'a>> {
+        let grid = *self;
+        let last_col = self.width.saturating_sub(1);
+        let starts = (0..self.width)
+            .map(|col| Point::new(0, col))
+            .chain((1..self.height).map(move |row| Point::new(row, last_col)));
+        starts.map(move |start| grid.line(start, Direction::SW))
+    }
+
+    /// The cells around `point`, in the order of [`Direction::ALL`].
+    pub fn neighbors(&self, point: Point) -> impl Iterator<Item = (Point, u8)> + 'a {
+        let grid = *self;
+        Direction::ALL.into_iter().filter_map(move |direction| {
+            let neighbor = point.offset(direction)?;
+            Some((neighbor, grid.get(neighbor)?))
+        })
+    }
+}
+
+/// Iterator over the cells of a straight line through a [`Grid`], created by [`Grid::line`].
+#[derive(Debug, Clone)]
+pub struct Line<'a> {
+    grid: Grid<'a>,
+    next: Option<Point>,
+    direction: Direction,
+}
+
+impl Iterator for Line<'_> {
+    type Item = (Point, u8);
+
+    fn next(&mut self) -> Option<(Point, u8)> {
+        let point = self.next?;
+        let Some(cell) = self.grid.get(point) else {
+            self.next = None;
+            return None;
+        };
+        self.next = point.offset(self.direction);
+        Some((point, cell))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+    use itertools::Itertools;
+
+    fn cells(line: Line<'_>) -> String {
+        line.map(|(_, cell)| cell as char).collect()
+    }
+
+    #[test]
+    fn dimensions_and_lookup() {
+        let grid = Grid::new(b"abc\ndef\n");
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.get(Point::new(1, 2)), Some(b'f'));
+        assert_eq!(grid.get(Point::new(0, 3)), None);
+        assert_eq!(grid.get(Point::new(2, 0)), None);
+        assert_eq!(
+            grid.get_step(Point::new(0, 0), Direction::SE, 1),
+            Some(b'e')
+        );
+        assert_eq!(grid.get_step(Point::new(0, 0), Direction::N, 1), None);
+        assert_eq!(grid.row_bytes(1), b"def");
+        assert_eq!(grid.row_bytes(2), b"");
+        assert_eq!(grid.get(Point::new(usize::MAX, 0)), None);
+        assert_eq!(grid.row_bytes(usize::MAX), b"");
+        assert!(!grid.reads(Point::new(usize::MAX, 0), Direction::E, b"a"));
+        assert!(grid.reads(Point::new(0, 2), Direction::SW, b"ce"));
+        assert!(grid.reads(Point::new(1, 0), Direction::E, b""));
+        assert!(!grid.reads(Point::new(1, 0), Direction::E, b"defg"));
+        assert!(!grid.reads(Point::new(1, 0), Direction::NW, b"da"));
+        assert!(grid.contains(Point::new(1, 0)));
+        assert!(!grid.contains(Point::new(0, 3)));
+
+        let grid = Grid::new(b"abc\ndef");
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.points().count(), 6);
+
+        let grid = Grid::new(b"");
+        assert_eq!((grid.width(), grid.height()), (0, 0));
+        assert_eq!(grid.points().count(), 0);
+    }
+
+    #[test]
+    fn lines() {
+        let grid = Grid::new(b"abc\ndef\nghi");
+        assert_eq!(
+            grid.rows().map(cells).collect::<Vec<_>>(),
+            ["abc", "def", "ghi"]
+        );
+        assert_eq!(
+            grid.columns().map(cells).collect::<Vec<_>>(),
+            ["adg", "beh", "cfi"]
+        );
+        assert_eq!(
+            grid.diagonals().map(cells).collect::<Vec<_>>(),
+            ["g", "dh", "aei", "bf", "c"]
+        );
+        assert_eq!(
+            grid.anti_diagonals().map(cells).collect::<Vec<_>>(),
+            ["a", "bd", "ceg", "fh", "i"]
+        );
+        assert_eq!(cells(grid.line(Point::new(2, 2), Direction::NW)), "iea");
+    }
+
+    #[test]
+    fn neighbors() {
+        let grid = Grid::new(b"abc\ndef\nghi");
+        assert_eq!(
+            grid.neighbors(Point::new(0, 0))
+                .map(|(_, cell)| cell as char)
+                .collect::<String>(),
+            "bed"
+        );
+        assert_eq!(grid.neighbors(Point::new(1, 1)).count(), 8);
+    }
+
+    #[test]
+    fn regions() {
+        let region = Region::new(Point::new(1, 2), 3, 2);
+        assert!(region.contains(Point::new(2, 4)));
+        assert!(!region.contains(Point::new(3, 4)));
+        assert!(!region.contains(Point::new(1, 1)));
+        assert_eq!(
+            region.points().collect::<Vec<_>>(),
+            [(1, 2), (1, 3), (1, 4), (2, 2), (2, 3), (2, 4)].map(|(row, col)| Point::new(row, col))
+        );
+
+        let grid = Grid::new(b"abcd\nefgh\nijkl\n");
+        assert_eq!(
+            region.intersect(grid.bounds()),
+            Region::new(Point::new(1, 2), 2, 2)
+        );
+        assert_eq!(
+            Region::new(Point::new(5, 0), 2, 2)
+                .intersect(grid.bounds())
+                .points()
+                .count(),
+            0
+        );
+
+        // Regions running past `usize::MAX` stop there.
+        let edge = Region::new(Point::new(usize::MAX - 1, 0), 2, 5);
+        assert!(edge.contains(Point::new(usize::MAX - 1, 1)));
+        assert!(!edge.contains(Point::new(usize::MAX, 0)));
+        assert_eq!(
+            edge.points().collect::<Vec<_>>(),
+            [Point::new(usize::MAX - 1, 0), Point::new(usize::MAX - 1, 1)]
+        );
+        assert_eq!(edge.intersect(grid.bounds()).points().count(), 0);
+    }
+
+    #[test]
+    fn ragged_rows() {
+        assert!(matches!(
+            check_rows(b"ab\ncd\n", Ragged::Reject),
+            Ok(Cow::Borrowed(b"ab\ncd\n"))
+        ));
+        assert!(matches!(
+            check_rows(b"ab\ncd", Ragged::Pad),
+            Ok(Cow::Borrowed(b"ab\ncd"))
+        ));
+        assert!(matches!(
+            check_rows(b"", Ragged::Reject),
+            Ok(Cow::Borrowed(b""))
+        ));
+
+        assert_eq!(
+            check_rows(b"abc\nde\nfgh\n", Ragged::Reject),
+            Err(Error::new(2, 3, ErrorKind::UnevenRows))
+        );
+        assert_eq!(
+            check_rows(b"abc\ndef\nghij", Ragged::Reject),
+            Err(Error::new(3, 4, ErrorKind::UnevenRows))
+        );
+        assert_eq!(
+            check_rows(b"abc\n\nghi", Ragged::Reject),
+            Err(Error::new(2, 1, ErrorKind::UnevenRows))
+        );
+
+        let padded = check_rows(b"ab\ncdef\n\ng", Ragged::Pad).unwrap();
+        assert_eq!(&*padded, b"ab  \ncdef\n    \ng   \n");
+        let grid = Grid::new(&padded);
+        assert_eq!((grid.width(), grid.height()), (4, 4));
+        assert_eq!(grid.get(Point::new(0, 3)), Some(EMPTY));
+    }
+
+    #[test]
+    fn finding_and_counting_words() {
+        let mut rng = XorShift::new(41);
+        let directions = [
+            Direction::ALL.as_slice(),
+            &[
+                Direction::new(1, 2),
+                Direction::new(-2, 0),
+                Direction::new(0, 0),
+            ],
+        ]
+        .concat();
+
+        for _ in 0..300 {
+            let width = 1 + rng.below(40) as usize;
+            let input = (0..1 + rng.below(8))
+                .map(|_| {
+                    (0..width)
+                        .map(|_| b"XMAS"[rng.below(4) as usize] as char)
+                        .collect::<String>()
+                })
+                .join("\n");
+            let grid = Grid::new(input.as_bytes());
+            let word = &b"XMAS"[..rng.below(5) as usize];
+
+            let mut found = Vec::new();
+            grid.find_word(word, &directions, |start, direction| {
+                found.push((start, direction))
+            });
+            let expected = grid
+                .points()
+                .flat_map(|start| directions.iter().map(move |&direction| (start, direction)))
+                .filter(|&(start, direction)| {
+                    !word.is_empty() && grid.reads(start, direction, word)
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(found, expected, "{input:?} {word:?}");
+            assert_eq!(
+                grid.count_word(word, &directions),
+                found.len(),
+                "{input:?} {word:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn even_rows_match_splitting() {
+        let mut rng = XorShift::new(19);
+
+        for _ in 0..5000 {
+            let input = (0..rng.below(12))
+                .map(|_| if rng.below(3) == 0 { b'\n' } else { b'a' })
+                .collect::<Vec<_>>();
+            let rows = input.strip_suffix(b"\n").unwrap_or(&input);
+            let even = rows.is_empty() || rows.split(|&c| c == b'\n').map(<[u8]>::len).all_equal();
+
+            assert_eq!(
+                check_rows(&input, Ragged::Reject).is_ok(),
+                even,
+                "{:?}",
+                String::from_utf8_lossy(&input)
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::borrow::Cow;
+
+/// Rewrites `\r\n` line endings to `\n` so solvers only ever see Unix line endings.
+///
+/// A lone `\r` at the very end is dropped as well: `aoc-runner` trims the final `\n` of the
+/// input, which leaves the `\r` of a trailing `\r\n` behind. Inputs that are already
+/// normalized are borrowed as-is.
+pub fn normalize_line_endings(input: &str) -> Cow<'_, str> {
+    let input = input.strip_suffix('\r').unwrap_or(input);
+
+    if memchr::memchr(b'\r', input.as_bytes()).is_none() {
+        Cow::Borrowed(input)
+    } else {
+        Cow::Owned(input.replace("\r\n", "\n"))
+    }
+}
+
+/// `input`, which must end in `\n`, with every combination of `\n`/`\r\n` line endings and
+/// present/trimmed final newline.
+#[cfg(test)]
+pub(crate) fn line_ending_variants(input: &str) -> [String; 4] {
+    let crlf = input.replace('\n', "\r\n");
+    [
+        input.to_string(),
+        input.trim_end_matches('\n').to_string(),
+        crlf.trim_end_matches('\n').to_string(),
+        crlf,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_line_endings() {
+        assert!(matches!(
+            normalize_line_endings("a\nb\n"),
+            Cow::Borrowed("a\nb\n")
+        ));
+        assert_eq!(normalize_line_endings("a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(normalize_line_endings("a\r\nb\r"), "a\nb");
+        assert_eq!(normalize_line_endings("a\rb"), "a\rb");
+    }
+}
@@ -5,5 +5,12 @@ pub mod day2;
 pub mod day3;
 pub mod day4;
 pub mod day5;
+pub mod error;
+pub mod grid;
+pub mod input;
+#[cfg(test)]
+mod test_util;
+
+pub use error::{Error, ErrorKind};
 
 aoc_lib! { year = 2024 }